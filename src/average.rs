@@ -0,0 +1,145 @@
+use chrono::{Local, NaiveDateTime};
+use std::collections::VecDeque;
+use std::time::Instant;
+
+use crate::meter::MeterReading;
+
+/// Rolling mean of `total_power` over the most recent readings.
+pub struct PowerAverage {
+    window: usize,
+    samples: VecDeque<f64>,
+}
+
+impl PowerAverage {
+    pub fn new(window: usize) -> Self {
+        Self {
+            window,
+            samples: VecDeque::with_capacity(window),
+        }
+    }
+
+    /// Add the reading's total power to the window and set `total_power_avg`.
+    /// Until the window has filled up, the available readings are averaged.
+    pub fn apply(&mut self, reading: &mut MeterReading) {
+        if self.samples.len() == self.window {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(reading.total_power);
+
+        let mean = self.samples.iter().sum::<f64>() / self.samples.len() as f64;
+        reading.total_power_avg = Some((mean * 100.0).round() / 100.0);
+    }
+}
+
+/// Average power derived from the growth of the consumption counter between
+/// readings, for meters whose instantaneous power is inaccurate.
+#[derive(Default)]
+pub struct ConsumptionRate {
+    /// `consumption_total_kwh` of the previous reading and when it was read.
+    previous: Option<(f64, Instant)>,
+}
+
+impl ConsumptionRate {
+    /// Set `avg_power_w` on the reading. The first reading has nothing to
+    /// compare against and a counter that went down (reset/replacement)
+    /// can't be trusted, so both leave it unset.
+    pub fn apply(&mut self, reading: &mut MeterReading) {
+        self.apply_at(reading, Instant::now());
+    }
+
+    /// Continue from a reading saved before a restart, dated by its
+    /// wall-clock timestamp. Readings that can't be dated are skipped.
+    pub fn resume(&mut self, previous: &MeterReading) {
+        let Ok(taken) = NaiveDateTime::parse_from_str(&previous.timestamp, "%Y-%m-%d %H:%M:%S%.f")
+        else {
+            return;
+        };
+        let age = (Local::now().naive_local() - taken).to_std().ok();
+        if let Some(then) = age.and_then(|age| Instant::now().checked_sub(age)) {
+            self.previous = Some((previous.consumption_total_kwh, then));
+        }
+    }
+
+    fn apply_at(&mut self, reading: &mut MeterReading, now: Instant) {
+        let kwh = reading.consumption_total_kwh;
+        let power = match self.previous {
+            Some((previous_kwh, then)) if kwh >= previous_kwh => {
+                let seconds = now.duration_since(then).as_secs_f64();
+                (seconds > 0.0).then(|| (kwh - previous_kwh) * 3.6e6 / seconds)
+            }
+            _ => None,
+        };
+
+        self.previous = Some((kwh, now));
+        reading.avg_power_w = power.map(|power| (power * 100.0).round() / 100.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn average(avg: &mut PowerAverage, total_power: f64) -> Option<f64> {
+        let mut reading = MeterReading {
+            total_power,
+            ..Default::default()
+        };
+        avg.apply(&mut reading);
+        reading.total_power_avg
+    }
+
+    #[test]
+    fn averages_last_readings() {
+        let mut avg = PowerAverage::new(3);
+        assert_eq!(average(&mut avg, 100.0), Some(100.0));
+        assert_eq!(average(&mut avg, 200.0), Some(150.0));
+        assert_eq!(average(&mut avg, 600.0), Some(300.0));
+        // The first reading drops out of the window
+        assert_eq!(average(&mut avg, 400.0), Some(400.0));
+    }
+
+    fn rate(rate: &mut ConsumptionRate, kwh: f64, at: Instant) -> Option<f64> {
+        let mut reading = MeterReading {
+            consumption_total_kwh: kwh,
+            ..Default::default()
+        };
+        rate.apply_at(&mut reading, at);
+        reading.avg_power_w
+    }
+
+    #[test]
+    fn power_from_counter_delta() {
+        let start = Instant::now();
+        let mut r = ConsumptionRate::default();
+        assert_eq!(rate(&mut r, 100.0, start), None);
+        // 0.01 kWh in 60 s is 600 W
+        assert_eq!(
+            rate(&mut r, 100.01, start + Duration::from_secs(60)),
+            Some(600.0)
+        );
+        // A counter reset reports nothing and becomes the new baseline
+        assert_eq!(rate(&mut r, 0.5, start + Duration::from_secs(120)), None);
+        assert_eq!(
+            rate(&mut r, 0.5, start + Duration::from_secs(180)),
+            Some(0.0)
+        );
+    }
+
+    #[test]
+    fn resumes_from_saved_reading() {
+        let mut r = ConsumptionRate::default();
+        r.resume(&MeterReading {
+            consumption_total_kwh: 100.0,
+            timestamp: (Local::now() - chrono::Duration::seconds(60))
+                .format("%Y-%m-%d %H:%M:%S%.6f")
+                .to_string(),
+            ..Default::default()
+        });
+        assert!((rate(&mut r, 100.01, Instant::now()).unwrap() - 600.0).abs() < 1.0);
+
+        let mut r = ConsumptionRate::default();
+        r.resume(&MeterReading::default());
+        assert_eq!(rate(&mut r, 100.01, Instant::now()), None);
+    }
+}
@@ -0,0 +1,284 @@
+use anyhow::Result;
+use log::{debug, info, warn};
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::meter::MeterReading;
+use crate::sink::ReadingSink;
+
+/// Consecutive failures after which publishing is paused.
+const FAILURE_THRESHOLD: u32 = 3;
+
+/// Pause after the first trip; doubled for every trip that follows without
+/// a successful publish in between.
+pub const BASE_COOLDOWN: Duration = Duration::from_secs(10);
+
+/// Upper bound for a single pause.
+const MAX_COOLDOWN: Duration = Duration::from_secs(300);
+
+/// Wraps a sink so an outage doesn't cost a failed attempt per reading.
+/// After [`FAILURE_THRESHOLD`] failures in a row the breaker opens and
+/// readings are only buffered until the cooldown is over; the next publish
+/// then tries the oldest buffered reading first and flushes the rest on
+/// success. The buffer drops its oldest readings when full. While one
+/// reader thread publishes, readings of the others are buffered for it.
+pub struct CircuitBreaker<S> {
+    inner: S,
+    capacity: usize,
+    cooldown: Duration,
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    /// Failed publishes since the last success.
+    failures: u32,
+    /// Times the breaker opened since the last success.
+    trips: u32,
+    open_until: Option<Instant>,
+    /// A reader thread is publishing the buffer; others only add to it.
+    flushing: bool,
+    /// Readings not published yet, oldest first, each entry a single
+    /// reading or a burst.
+    pending: VecDeque<Vec<MeterReading>>,
+}
+
+impl<S: ReadingSink> CircuitBreaker<S> {
+    pub fn new(inner: S, capacity: usize, cooldown: Duration) -> Self {
+        Self {
+            inner,
+            capacity: capacity.max(1),
+            cooldown,
+            state: Mutex::new(State::default()),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().expect("circuit breaker lock poisoned")
+    }
+
+    fn push(&self, state: &mut State, readings: &[MeterReading]) {
+        state.pending.push_back(readings.to_vec());
+        self.trim(state);
+    }
+
+    fn trim(&self, state: &mut State) {
+        while state.pending.len() > 1
+            && state.pending.iter().map(Vec::len).sum::<usize>() > self.capacity
+        {
+            state.pending.pop_front();
+            debug!(
+                "{} buffer full, dropped the oldest reading",
+                self.inner.name()
+            );
+        }
+    }
+
+    fn send(&self, readings: &[MeterReading]) -> Result<()> {
+        let mut state = self.lock();
+        self.push(&mut state, readings);
+        if state.flushing {
+            debug!(
+                "Publishing to {} in progress, buffered reading",
+                self.name()
+            );
+            return Ok(());
+        }
+        if state.open_until.is_some_and(|until| Instant::now() < until) {
+            debug!("Publishing to {} is paused, buffered reading", self.name());
+            return Ok(());
+        }
+        state.open_until = None;
+        state.flushing = true;
+
+        while let Some(next) = state.pending.pop_front() {
+            // A publish can take up to the publish timeout per broker, don't
+            // keep the reader threads of other meters waiting meanwhile
+            drop(state);
+            let published = match next.as_slice() {
+                [reading] => self.inner.publish(reading),
+                burst => self.inner.publish_batch(burst),
+            };
+            state = self.lock();
+            if let Err(e) = published {
+                state.flushing = false;
+                state.pending.push_front(next);
+                self.trim(&mut state);
+                state.failures += 1;
+                // Once tripped, a single failed retry pauses again
+                if state.trips > 0 || state.failures >= FAILURE_THRESHOLD {
+                    let factor = 1u32.checked_shl(state.trips).unwrap_or(u32::MAX);
+                    let cooldown = self.cooldown.saturating_mul(factor).min(MAX_COOLDOWN);
+                    state.trips += 1;
+                    state.open_until = Some(Instant::now() + cooldown);
+                    warn!(
+                        "Publishing to {} failed {} times in a row, pausing for {}s ({} readings buffered)",
+                        self.name(),
+                        state.failures,
+                        cooldown.as_secs(),
+                        state.pending.iter().map(Vec::len).sum::<usize>()
+                    );
+                }
+                return Err(e);
+            }
+            if state.failures > 0 {
+                info!(
+                    "Publishing to {} recovered after {} failures",
+                    self.name(),
+                    state.failures
+                );
+                state.failures = 0;
+                state.trips = 0;
+            }
+        }
+        state.flushing = false;
+        Ok(())
+    }
+}
+
+impl<S: ReadingSink> ReadingSink for CircuitBreaker<S> {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    fn publish(&self, reading: &MeterReading) -> Result<()> {
+        self.send(std::slice::from_ref(reading))
+    }
+
+    fn publish_batch(&self, readings: &[MeterReading]) -> Result<()> {
+        self.send(readings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    /// Records the total power of published readings; fails while `down`.
+    #[derive(Default)]
+    struct Flaky {
+        down: AtomicBool,
+        attempts: Mutex<u32>,
+        published: Mutex<Vec<f64>>,
+    }
+
+    impl ReadingSink for &Flaky {
+        fn name(&self) -> &str {
+            "flaky"
+        }
+
+        fn publish(&self, reading: &MeterReading) -> Result<()> {
+            *self.attempts.lock().unwrap() += 1;
+            if self.down.load(Ordering::SeqCst) {
+                anyhow::bail!("broker down");
+            }
+            self.published.lock().unwrap().push(reading.total_power);
+            Ok(())
+        }
+    }
+
+    fn reading(total_power: f64) -> MeterReading {
+        MeterReading {
+            total_power,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn pauses_after_failures_and_flushes_on_recovery() {
+        let sink = Flaky::default();
+        let cooldown = Duration::from_millis(50);
+        let breaker = CircuitBreaker::new(&sink, 4, cooldown);
+
+        sink.down.store(true, Ordering::SeqCst);
+        // Each failure retries the oldest buffered reading first
+        for power in [1.0, 2.0, 3.0] {
+            assert!(breaker.publish(&reading(power)).is_err());
+        }
+        assert_eq!(*sink.attempts.lock().unwrap(), 3);
+
+        // Open: buffered without an attempt, the oldest dropped beyond 4
+        for power in [4.0, 5.0] {
+            assert!(breaker.publish(&reading(power)).is_ok());
+        }
+        assert_eq!(*sink.attempts.lock().unwrap(), 3);
+
+        sink.down.store(false, Ordering::SeqCst);
+        std::thread::sleep(cooldown);
+        breaker.publish(&reading(6.0)).unwrap();
+        assert_eq!(*sink.published.lock().unwrap(), [3.0, 4.0, 5.0, 6.0]);
+    }
+
+    #[test]
+    fn failed_retry_pauses_longer() {
+        let sink = Flaky::default();
+        let cooldown = Duration::from_millis(50);
+        let breaker = CircuitBreaker::new(&sink, 10, cooldown);
+
+        sink.down.store(true, Ordering::SeqCst);
+        for power in [1.0, 2.0, 3.0] {
+            assert!(breaker.publish(&reading(power)).is_err());
+        }
+        std::thread::sleep(cooldown);
+        // One failed retry is enough to open again, for twice as long
+        assert!(breaker.publish(&reading(4.0)).is_err());
+        std::thread::sleep(cooldown);
+        assert!(breaker.publish(&reading(5.0)).is_ok());
+        assert_eq!(*sink.attempts.lock().unwrap(), 4);
+    }
+
+    #[test]
+    fn bursts_stay_together() {
+        let sink = Flaky::default();
+        let breaker = CircuitBreaker::new(&sink, 3, Duration::from_millis(50));
+
+        sink.down.store(true, Ordering::SeqCst);
+        assert!(breaker.publish(&reading(1.0)).is_err());
+        assert!(breaker
+            .publish_batch(&[reading(2.0), reading(3.0)])
+            .is_err());
+        sink.down.store(false, Ordering::SeqCst);
+        // Room for 3 readings: the single one goes, the bursts are kept whole
+        breaker.publish_batch(&[reading(4.0)]).unwrap();
+        assert_eq!(*sink.published.lock().unwrap(), [2.0, 3.0, 4.0]);
+    }
+
+    /// Takes `delay` to publish, like a broker that stalls.
+    struct Slow {
+        delay: Duration,
+        published: Mutex<Vec<f64>>,
+    }
+
+    impl ReadingSink for &Slow {
+        fn name(&self) -> &str {
+            "slow"
+        }
+
+        fn publish(&self, reading: &MeterReading) -> Result<()> {
+            std::thread::sleep(self.delay);
+            self.published.lock().unwrap().push(reading.total_power);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn slow_publish_does_not_block_other_readers() {
+        let sink = Slow {
+            delay: Duration::from_millis(300),
+            published: Mutex::default(),
+        };
+        let breaker = CircuitBreaker::new(&sink, 10, Duration::from_millis(50));
+
+        std::thread::scope(|scope| {
+            scope.spawn(|| breaker.publish(&reading(1.0)).unwrap());
+            std::thread::sleep(Duration::from_millis(50));
+            let started = Instant::now();
+            breaker.publish(&reading(2.0)).unwrap();
+            assert!(started.elapsed() < Duration::from_millis(200));
+        });
+        // The first reader flushed the reading buffered meanwhile
+        assert_eq!(*sink.published.lock().unwrap(), [1.0, 2.0]);
+    }
+}
@@ -1,11 +1,42 @@
-use clap::Parser;
+use anyhow::{bail, Context, Result};
+use clap::parser::ValueSource;
+use clap::{ArgMatches, CommandFactory, FromArgMatches, Parser};
+use regex::Regex;
+use std::ffi::OsString;
+use std::fs;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::logging::LogFormat;
+use crate::meter::{PowerSettings, DEFAULT_POWER_PRECISION};
+use crate::mqtt::{self, Encoding, JsonCase, PayloadFormat};
+use crate::obis::{self, Field, ObisMap};
+use crate::probe::{LineControl, SerialSettings, DEFAULT_PORT_PATTERN};
+use crate::profile::detect_meter_profile;
+use crate::protocol::{CrcCheck, Mode, TelegramOptions, DEFAULT_MAX_TELEGRAM_LINES};
 
 #[derive(Parser, Debug)]
 #[command(
     name = "energymon",
-    about = "ISk5MT174 electricity meter reader via IR optical head"
+    about = "ISk5MT174 electricity meter reader via IR optical head",
+    args_override_self = true
 )]
 pub struct Config {
+    /// Log more: -v for info, -vv for debug, -vvv for trace. RUST_LOG,
+    /// if set, takes precedence
+    #[arg(short, long, action = clap::ArgAction::Count)]
+    pub verbose: u8,
+
+    /// Log line format
+    #[arg(long, value_enum, default_value_t = LogFormat::Text)]
+    pub log_format: LogFormat,
+
+    /// TOML config file; keys mirror the option names (e.g. mqtt_host) and
+    /// command-line flags override values from the file
+    #[arg(long)]
+    pub config: Option<PathBuf>,
+
     /// MQTT broker hostname
     #[arg(long, default_value = "127.0.0.1")]
     pub mqtt_host: String,
@@ -14,27 +45,758 @@ pub struct Config {
     #[arg(long, default_value_t = 1883)]
     pub mqtt_port: u16,
 
+    /// Additional MQTT broker as host[:port]; readings are published to
+    /// every broker given (repeatable, replaces --mqtt-host/--mqtt-port)
+    #[arg(long, value_name = "HOST:PORT", value_parser = parse_broker)]
+    pub mqtt_broker: Vec<(String, u16)>,
+
     /// MQTT client ID
     #[arg(long, default_value = "ISK5MT174-DATA")]
     pub mqtt_client_id: String,
 
-    /// MQTT topic to publish to
-    #[arg(long, default_value = "tele/ISK5MT174")]
+    /// MQTT keep-alive interval in seconds; should exceed the reading
+    /// interval, as the connection is only serviced when publishing
+    #[arg(long, default_value_t = 60, value_parser = clap::value_parser!(u64).range(1..))]
+    pub mqtt_keepalive_secs: u64,
+
+    /// MQTT username (enables authentication when set)
+    #[arg(long)]
+    pub mqtt_username: Option<String>,
+
+    /// MQTT password
+    #[arg(long, env = "ENERGYMON_MQTT_PASSWORD", hide_env_values = true)]
+    pub mqtt_password: Option<String>,
+
+    /// MQTT topic to publish to; may contain {device_id}, {client_id} and,
+    /// for --split-topics, {field}
+    #[arg(long, default_value = "tele/ISK5MT174", value_parser = mqtt::parse_topic_template)]
     pub mqtt_topic: String,
 
-    /// Device identifier substring to match in meter response
+    /// Topic for the retained Online/Offline availability message
+    /// [default: <mqtt-topic>/LWT]
+    #[arg(long)]
+    pub mqtt_lwt_topic: Option<String>,
+
+    /// MQTT QoS level (0 = at most once, 1 = at least once, 2 = exactly once)
+    #[arg(long, default_value_t = 0, value_parser = clap::value_parser!(u8).range(0..=2))]
+    pub mqtt_qos: u8,
+
+    /// Publish readings with the MQTT retain flag set
+    #[arg(long)]
+    pub mqtt_retain: bool,
+
+    /// Seconds to wait for a publish to go out (or be acknowledged) before
+    /// giving up on it
+    #[arg(long, default_value_t = 5, value_parser = clap::value_parser!(u64).range(1..))]
+    pub mqtt_publish_timeout_secs: u64,
+
+    /// Readings kept while publishing is paused after repeated MQTT
+    /// failures, published once the broker is back; the oldest are dropped
+    /// first
+    #[arg(long, default_value_t = 100, value_parser = clap::value_parser!(u64).range(1..))]
+    pub publish_buffer_size: u64,
+
+    /// Print readings as JSON to stdout instead of publishing to MQTT
+    #[arg(long, visible_alias = "stdout")]
+    pub dry_run: bool,
+
+    /// Encoding of the reading published to MQTT; msgpack is binary, so
+    /// subscribers must decode it (stdout and split topics stay text)
+    #[arg(long, value_enum, default_value_t = Encoding::Json, value_name = "ENCODING")]
+    pub format: Encoding,
+
+    /// Key naming of the JSON payload
+    #[arg(long, value_enum, default_value_t = JsonCase::Snake)]
+    pub json_case: JsonCase,
+
+    /// Flatten nested payload values into dot-joined keys (`a.b`), for
+    /// time series databases that only take flat objects
+    #[arg(long)]
+    pub json_flat: bool,
+
+    /// Add a `units` object mapping each field to its unit, e.g. kWh or V
+    #[arg(long)]
+    pub with_units: bool,
+
+    /// Only publish these comma-separated fields; device_id and timestamp
+    /// are always kept
+    #[arg(long, value_delimiter = ',', value_parser = mqtt::parse_field_name, value_name = "FIELDS")]
+    pub include_fields: Vec<String>,
+
+    /// Leave these comma-separated fields out of published readings
+    #[arg(long, value_delimiter = ',', value_parser = mqtt::parse_field_name, value_name = "FIELDS")]
+    pub exclude_fields: Vec<String>,
+
+    /// Also publish each field as a plain value to `<topic>/<field>`
+    #[arg(long)]
+    pub split_topics: bool,
+
+    /// Only publish the numeric fields that changed since the meter's
+    /// previous reading, plus device_id and timestamp; the first reading is
+    /// complete. Not for retained or Home Assistant discovered topics
+    #[arg(long)]
+    pub delta_only: bool,
+
+    /// Publish Home Assistant MQTT discovery configs on the first reading
+    #[arg(long)]
+    pub homeassistant_discovery: bool,
+
+    /// Serve Prometheus metrics on this address (e.g. 0.0.0.0:9100)
+    #[arg(long)]
+    pub prometheus_listen: Option<SocketAddr>,
+
+    /// Append each reading as a row to this CSV file
+    #[arg(long)]
+    pub csv_path: Option<PathBuf>,
+
+    /// Insert each reading as a row into the `readings` table of this
+    /// SQLite database, created if needed
+    #[arg(long)]
+    pub sqlite_path: Option<PathBuf>,
+
+    /// Keep the latest reading in this file so cost totals and consumption
+    /// deltas carry on after a restart
+    #[arg(long, value_name = "PATH")]
+    pub state_file: Option<PathBuf>,
+
+    /// Stream each reading as a line of JSON to clients of this Unix socket
+    #[arg(long, value_name = "PATH")]
+    pub unix_socket: Option<PathBuf>,
+
+    /// Write each reading as a line of JSON to this named pipe, created if
+    /// missing; readings are dropped while no reader is attached
+    #[arg(long, value_name = "PATH")]
+    pub pipe_path: Option<PathBuf>,
+
+    /// Device identifier substring to match in meter response, in any case
     #[arg(long, default_value = "ISk5MT174")]
     pub device_id: String,
 
-    /// Serial port path (if omitted, probes all /dev/ttyUSB* ports)
+    /// Regular expression the meter identification (without the leading /)
+    /// must match instead of containing --device-id, e.g. ^ISk5MT174-0001$
+    #[arg(long, value_name = "REGEX")]
+    pub device_id_regex: Option<Regex>,
+
+    /// Serial port path (if omitted, probes all ports matching --port-pattern).
+    /// Repeat or comma-separate to read several meters at once, one thread per port
+    #[arg(long, value_delimiter = ',')]
+    pub port: Vec<String>,
+
+    /// Drive DTR high after opening the port; the Weidmann IR head is
+    /// powered from it
+    #[arg(long, default_value_t = true, action = clap::ArgAction::Set, value_name = "BOOL")]
+    pub dtr: bool,
+
+    /// Drive RTS high after opening the port; some self-powered heads need
+    /// it low
+    #[arg(long, default_value_t = true, action = clap::ArgAction::Set, value_name = "BOOL")]
+    pub rts: bool,
+
+    /// Pulse DTR low before setting it, to reset heads that need it
+    #[arg(long)]
+    pub dtr_pulse: bool,
+
+    /// When probing finds no meter at 300 baud, retry at 600..19200 baud for
+    /// meters that ignore the baud negotiation (slows down probing)
+    #[arg(long)]
+    pub baud_scan: bool,
+
+    /// Keep reading the telegrams a woken meter streams, only sending the
+    /// init sequence again after a failed read
+    #[arg(long)]
+    pub persistent_session: bool,
+
+    /// Read N telegrams back to back and publish them together, as one JSON
+    /// array on MQTT; best combined with --persistent-session
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..))]
+    pub burst: Option<u64>,
+
+    /// Read the meter on --port without checking that its identification
+    /// line contains the device ID
+    #[arg(long)]
+    pub no_verify: bool,
+
+    /// Print the available serial ports and exit; ports that would be
+    /// probed are marked with `*`
     #[arg(long)]
-    pub port: Option<String>,
+    pub list_ports: bool,
+
+    /// Port names to probe: a substring, or a glob with * and ?. Repeat or
+    /// comma-separate for several patterns
+    #[arg(long, value_delimiter = ',', default_value = DEFAULT_PORT_PATTERN)]
+    pub port_pattern: Vec<String>,
+
+    /// Read telegrams from a captured file or a tcp://host:port stream
+    /// (e.g. ser2net) instead of a serial port
+    #[arg(long, value_parser = parse_source, conflicts_with = "port")]
+    pub source: Option<Source>,
+
+    /// Read from a simulated meter instead of real hardware, for development
+    #[arg(long, conflicts_with_all = ["port", "source"])]
+    pub simulate: bool,
+
+    /// Protocol mode: c requests each telegram, d listens for pushed telegrams
+    #[arg(long, value_enum, default_value_t = Mode::C)]
+    pub mode: Mode,
+
+    /// Fixed baud rate used in mode d
+    #[arg(long, default_value_t = 2400)]
+    pub mode_d_baud: u32,
 
     /// Serial read timeout in seconds
     #[arg(long, default_value_t = 10)]
     pub timeout_secs: u64,
 
+    /// Seconds to wait for the identification line while probing ports
+    #[arg(long, default_value_t = 6, value_parser = clap::value_parser!(u64).range(1..))]
+    pub probe_timeout_secs: u64,
+
     /// Interval between readings in seconds
     #[arg(long, default_value_t = 1)]
     pub interval_secs: u64,
+
+    /// Milliseconds to wait for the meter to wake up after the init sequence
+    #[arg(long, default_value_t = 500)]
+    pub init_wake_ms: u64,
+
+    /// Number of times a failed read is retried before reconnecting
+    #[arg(long, default_value_t = 3)]
+    pub max_retries: u32,
+
+    /// Initial retry delay in milliseconds, doubled after each failed attempt
+    #[arg(long, default_value_t = 1000)]
+    pub retry_base_ms: u64,
+
+    /// Reconnect when no telegram was read successfully for this many seconds
+    #[arg(long, value_name = "SECS")]
+    pub watchdog_secs: Option<u64>,
+
+    /// Publish a heartbeat to <topic>/status every this many seconds, even
+    /// while the meter doesn't answer
+    #[arg(long, value_name = "SECS", value_parser = clap::value_parser!(u64).range(1..))]
+    pub heartbeat_secs: Option<u64>,
+
+    /// Publish reader metrics (reads, errors, reconnects) to <topic>/metrics
+    /// every this many readings
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..))]
+    pub metrics_every: Option<u64>,
+
+    /// Skip readings identical to the previous one (ignoring the timestamp)
+    #[arg(long)]
+    pub dedup: bool,
+
+    /// Also publish `total_power_avg`, the mean total power of the last N readings
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    pub avg_window: Option<u32>,
+
+    /// Warn and publish `stale: true` once the energy counters haven't moved
+    /// for N readings while the meter reports power
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    pub stale_after: Option<u32>,
+
+    /// Read and publish a single telegram, then exit (non-zero on failure)
+    #[arg(long)]
+    pub once: bool,
+
+    /// Price per kWh for tariff 1 consumption, used for cost estimates
+    #[arg(long)]
+    pub price_t1: Option<f64>,
+
+    /// Price per kWh for tariff 2 consumption, used for cost estimates
+    #[arg(long)]
+    pub price_t2: Option<f64>,
+
+    /// Verify the block check character (BCC) of each telegram and reject
+    /// readings that fail (some meters omit the BCC, so this is off by default)
+    #[arg(long)]
+    pub verify_bcc: bool,
+
+    /// Verify the checksum on the `!` line and reject readings that fail;
+    /// only DSMR P1 telegrams carry one
+    #[arg(long, value_enum, value_name = "KIND")]
+    pub crc_check: Option<CrcCheck>,
+
+    /// Append every raw telegram, as received, to this file for debugging.
+    /// The file can be replayed with --source
+    #[arg(long)]
+    pub raw_log: Option<PathBuf>,
+
+    /// Fail on a telegram from another device instead of skipping it
+    #[arg(long)]
+    pub strict_device_id: bool,
+
+    /// Comma-separated OBIS codes the telegram should contain; unexpected
+    /// and missing codes are logged as warnings
+    #[arg(long, value_delimiter = ',')]
+    pub expected_obis: Vec<String>,
+
+    /// Abort a telegram that hasn't ended after this many lines
+    #[arg(long, default_value_t = DEFAULT_MAX_TELEGRAM_LINES)]
+    pub max_telegram_lines: usize,
+
+    /// Read a field from a different OBIS code, e.g.
+    /// consumption_total_kwh=1-8:1.8.0 (repeatable)
+    #[arg(long, value_name = "FIELD=CODE", value_parser = obis::parse_override)]
+    pub obis_map: Vec<(Field, String)>,
+
+    /// Report zero power for phases below this voltage, e.g. 50 for the
+    /// unconnected phases of a single-phase install
+    #[arg(long, value_name = "VOLTS", default_value_t = 0.0)]
+    pub min_phase_voltage: f64,
+
+    /// First character of a telegram's identification line
+    #[arg(long, value_name = "CHAR", default_value_t = '/')]
+    pub telegram_start: char,
+
+    /// First character of a telegram's end line
+    #[arg(long, value_name = "CHAR", default_value_t = '!')]
+    pub telegram_end: char,
+
+    /// Timezone of the meter clock (0-0:1.0.0), e.g. Europe/Amsterdam;
+    /// defaults to the host's
+    #[arg(long, value_name = "TZ")]
+    pub meter_tz: Option<chrono_tz::Tz>,
+
+    /// Decimals calculated power values are rounded to; 0 gives whole
+    /// watts, -1 rounds to tens
+    #[arg(long, value_name = "N", default_value_t = DEFAULT_POWER_PRECISION, allow_negative_numbers = true)]
+    pub power_precision: i32,
+
+    /// Log a hex dump of every byte received from the meter (use with -v)
+    #[arg(long)]
+    pub hexdump: bool,
+}
+
+/// Parse the command line, merging in values from `--config` if given.
+///
+/// The file is turned into arguments placed before the real command line, so
+/// clap applies defaults, validation and "last value wins" for overrides.
+/// Settings the command line also gives are left out of the file arguments,
+/// as list and counted options would add up instead.
+pub fn load() -> Result<Config> {
+    let cli_args: Vec<OsString> = std::env::args_os().collect();
+    let matches = Config::command().get_matches_from(&cli_args);
+    let cli = Config::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    let Some(path) = &cli.config else {
+        cli.validate()?;
+        return Ok(cli);
+    };
+
+    let contents = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read config file {}", path.display()))?;
+    let file_args = file_args(&contents, &matches)
+        .with_context(|| format!("Invalid config file {}", path.display()))?;
+
+    let mut args = cli_args[..1].to_vec();
+    args.extend(file_args.into_iter().map(OsString::from));
+    args.extend(cli_args[1..].iter().cloned());
+    let config = Config::parse_from(args);
+    config.validate()?;
+    Ok(config)
+}
+
+/// Convert a TOML config file into the equivalent command-line arguments,
+/// skipping the ones already set on the command line `cli`.
+fn file_args(contents: &str, cli: &ArgMatches) -> Result<Vec<String>> {
+    let table: toml::Table = toml::from_str(contents)?;
+    let command = Config::command();
+    let mut args = Vec::new();
+
+    for (key, value) in table {
+        let long = key.replace('_', "-");
+        let Some(arg) = command
+            .get_arguments()
+            .find(|arg| arg.get_long() == Some(long.as_str()))
+            .filter(|_| long != "config")
+        else {
+            bail!("Unknown key `{}`", key);
+        };
+        if cli.value_source(arg.get_id().as_str()) == Some(ValueSource::CommandLine) {
+            continue;
+        }
+        let flag = format!("--{}", long);
+
+        // Counted flags like `verbose = 2` repeat the flag instead of taking a value
+        if let (clap::ArgAction::Count, toml::Value::Integer(n)) = (arg.get_action(), &value) {
+            args.extend((0..*n).map(|_| flag.clone()));
+            continue;
+        }
+
+        let values = match value {
+            toml::Value::Array(values) => values,
+            value => vec![value],
+        };
+        for value in values {
+            match value {
+                // Options like `dtr = false` take the value, switches are
+                // only passed when true
+                toml::Value::Boolean(b) if arg.get_action().takes_values() => {
+                    args.extend([flag.clone(), b.to_string()])
+                }
+                toml::Value::Boolean(true) => args.push(flag.clone()),
+                toml::Value::Boolean(false) => {}
+                toml::Value::String(s) => args.extend([flag.clone(), s]),
+                toml::Value::Integer(i) => args.extend([flag.clone(), i.to_string()]),
+                toml::Value::Float(f) => args.extend([flag.clone(), f.to_string()]),
+                other => bail!("Unsupported value for `{}`: {}", key, other),
+            }
+        }
+    }
+
+    Ok(args)
+}
+
+/// A non-serial telegram source.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Source {
+    /// A file containing captured telegrams.
+    File(PathBuf),
+    /// A `host:port` address streaming telegrams over TCP.
+    Tcp(String),
+}
+
+fn parse_source(s: &str) -> Result<Source, String> {
+    match s.strip_prefix("tcp://") {
+        Some("") => Err("missing host:port after tcp://".to_string()),
+        Some(addr) => Ok(Source::Tcp(addr.to_string())),
+        None => Ok(Source::File(PathBuf::from(s))),
+    }
+}
+
+/// Parse a `host[:port]` broker address, defaulting to port 1883.
+fn parse_broker(s: &str) -> Result<(String, u16), String> {
+    let (host, port) = match s.rsplit_once(':') {
+        Some((host, port)) => (
+            host,
+            port.parse()
+                .map_err(|_| format!("invalid port in `{}`", s))?,
+        ),
+        None => (s, 1883),
+    };
+    if host.is_empty() {
+        return Err(format!("missing host in `{}`", s));
+    }
+    Ok((host.to_string(), port))
+}
+
+impl Config {
+    /// Every broker to publish to: the `--mqtt-broker` list, or
+    /// `--mqtt-host`/`--mqtt-port` if none was given.
+    pub fn mqtt_brokers(&self) -> Vec<(String, u16)> {
+        if self.mqtt_broker.is_empty() {
+            vec![(self.mqtt_host.clone(), self.mqtt_port)]
+        } else {
+            self.mqtt_broker.clone()
+        }
+    }
+
+    /// Payload naming and field selection derived from the command line.
+    pub fn payload_format(&self) -> PayloadFormat {
+        PayloadFormat {
+            encoding: self.format,
+            case: self.json_case,
+            include_fields: self.include_fields.clone(),
+            exclude_fields: self.exclude_fields.clone(),
+            flat: self.json_flat,
+            units: self.with_units,
+        }
+    }
+
+    /// A warning if the broker is likely to drop the MQTT connection between
+    /// publishes. It is only reconnected on the next publish, so this works,
+    /// just with a reconnect per reading.
+    pub fn keepalive_warning(&self) -> Option<String> {
+        // Keep-alive pings only go out while publishing, so a broker drops
+        // a connection that idles for longer than the keep-alive
+        let idle = self
+            .heartbeat_secs
+            .map_or(self.interval_secs, |secs| secs.min(self.interval_secs));
+        (!self.dry_run && !self.once && self.mqtt_keepalive_secs <= idle).then(|| {
+            format!(
+                "--mqtt-keepalive-secs ({}) should be larger than the publish interval ({}s)",
+                self.mqtt_keepalive_secs, idle
+            )
+        })
+    }
+
+    /// Checks across options that clap can't express.
+    fn validate(&self) -> Result<()> {
+        // A retained diff or a discovery template reading a field the diff
+        // left out would show the field as unavailable
+        if self.delta_only && (self.mqtt_retain || self.homeassistant_discovery) {
+            bail!("--delta-only can't be combined with --mqtt-retain or --homeassistant-discovery");
+        }
+        Ok(())
+    }
+
+    /// Serial timing settings derived from the command line.
+    pub fn serial_settings(&self) -> SerialSettings {
+        SerialSettings {
+            init_wake: Duration::from_millis(self.init_wake_ms),
+            probe_timeout: Duration::from_secs(self.probe_timeout_secs),
+            port_patterns: self.port_pattern.clone(),
+            lines: LineControl {
+                dtr: self.dtr,
+                rts: self.rts,
+                dtr_pulse: self.dtr_pulse,
+            },
+            baud_scan: self.baud_scan,
+            persistent_session: self.persistent_session,
+            verify_ident: !self.no_verify,
+            device_pattern: self.device_id_regex.clone(),
+            start: self.telegram_start,
+            data_format: detect_meter_profile(&self.device_id).data_format,
+        }
+    }
+
+    /// Log level selected with -v, if any.
+    pub fn log_level(&self) -> Option<log::LevelFilter> {
+        match self.verbose {
+            0 => None,
+            1 => Some(log::LevelFilter::Info),
+            2 => Some(log::LevelFilter::Debug),
+            _ => Some(log::LevelFilter::Trace),
+        }
+    }
+
+    /// Telegram read options derived from the command line.
+    pub fn telegram_options(&self) -> TelegramOptions {
+        TelegramOptions {
+            verify_bcc: self.verify_bcc,
+            crc_check: self.crc_check,
+            raw_log: self.raw_log.clone(),
+            strict_device_id: self.strict_device_id,
+            device_pattern: self.device_id_regex.clone(),
+            expected_obis: self.expected_obis.clone(),
+            max_lines: self.max_telegram_lines,
+            obis_map: ObisMap::with_overrides(&self.obis_map),
+            power: PowerSettings {
+                min_phase_voltage: self.min_phase_voltage,
+                precision: self.power_precision,
+            },
+            hexdump: self.hexdump,
+            meter_tz: self.meter_tz,
+            start: self.telegram_start,
+            end: self.telegram_end,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::probe::DEFAULT_PROBE_TIMEOUT;
+
+    #[test]
+    fn parse_source_tcp() {
+        assert_eq!(
+            parse_source("tcp://192.168.1.5:2001"),
+            Ok(Source::Tcp("192.168.1.5:2001".to_string()))
+        );
+        assert!(parse_source("tcp://").is_err());
+    }
+
+    #[test]
+    fn mqtt_broker_list() {
+        assert_eq!(
+            parse_broker("remote:8883"),
+            Ok(("remote".to_string(), 8883))
+        );
+        assert_eq!(parse_broker("local"), Ok(("local".to_string(), 1883)));
+        assert!(parse_broker(":1883").is_err());
+        assert!(parse_broker("remote:mqtt").is_err());
+
+        let config = Config::try_parse_from(["energymon"]).unwrap();
+        assert_eq!(config.mqtt_brokers(), [("127.0.0.1".to_string(), 1883)]);
+        let config = Config::try_parse_from([
+            "energymon",
+            "--mqtt-broker",
+            "local",
+            "--mqtt-broker",
+            "remote:8883",
+        ])
+        .unwrap();
+        assert_eq!(config.mqtt_brokers().len(), 2);
+    }
+
+    #[test]
+    fn parse_source_file() {
+        assert_eq!(
+            parse_source("captures/telegram.txt"),
+            Ok(Source::File(PathBuf::from("captures/telegram.txt")))
+        );
+    }
+
+    /// Matches of a command line without arguments.
+    fn no_cli() -> ArgMatches {
+        Config::command().get_matches_from(["energymon"])
+    }
+
+    #[test]
+    fn file_args_from_toml() {
+        let args = file_args(
+            r#"
+            mqtt_host = "broker.local"
+            mqtt_port = 1884
+            mqtt_retain = true
+            dry_run = false
+            "#,
+            &no_cli(),
+        )
+        .unwrap();
+        assert_eq!(
+            args,
+            [
+                "--mqtt-host",
+                "broker.local",
+                "--mqtt-port",
+                "1884",
+                "--mqtt-retain"
+            ]
+        );
+    }
+
+    #[test]
+    fn file_args_unknown_key() {
+        let err = file_args("mqtt_hots = \"x\"", &no_cli()).unwrap_err();
+        assert!(err.to_string().contains("mqtt_hots"));
+    }
+
+    #[test]
+    fn file_args_malformed() {
+        assert!(file_args("mqtt_host = ", &no_cli()).is_err());
+    }
+
+    #[test]
+    fn cli_overrides_file() {
+        // Like `load`, with the file arguments before the command line
+        let merged = |file: &str, cli: &[&str]| {
+            let cli = [&["energymon"], cli].concat();
+            let matches = Config::command().get_matches_from(&cli);
+            let mut args = vec!["energymon".to_string()];
+            args.extend(file_args(file, &matches).unwrap());
+            args.extend(cli[1..].iter().map(|arg| arg.to_string()));
+            Config::try_parse_from(args).unwrap()
+        };
+
+        let config = merged(
+            "mqtt_host = \"broker.local\"\nmqtt_port = 1884",
+            &["--mqtt-port", "1999"],
+        );
+        assert_eq!(config.mqtt_host, "broker.local");
+        assert_eq!(config.mqtt_port, 1999);
+
+        // Lists and counts are replaced, not added to
+        let config = merged(
+            "port = [\"/dev/ttyUSB0\"]\nverbose = 2",
+            &["--port", "/dev/ttyUSB9", "-v"],
+        );
+        assert_eq!(config.port, ["/dev/ttyUSB9"]);
+        assert_eq!(config.verbose, 1);
+        let config = merged("port = [\"/dev/ttyUSB0\"]\nverbose = 2", &[]);
+        assert_eq!(config.port, ["/dev/ttyUSB0"]);
+        assert_eq!(config.verbose, 2);
+    }
+
+    #[test]
+    fn port_list() {
+        let config = Config::parse_from([
+            "energymon",
+            "--port",
+            "/dev/ttyUSB0,/dev/ttyUSB1",
+            "--port",
+            "/dev/ttyUSB2",
+        ]);
+        assert_eq!(
+            config.port,
+            ["/dev/ttyUSB0", "/dev/ttyUSB1", "/dev/ttyUSB2"]
+        );
+    }
+
+    #[test]
+    fn line_control() {
+        let config = Config::try_parse_from(["energymon"]).unwrap();
+        assert_eq!(config.serial_settings().lines, LineControl::default());
+
+        let mut args = vec!["energymon".to_string()];
+        args.extend(file_args("rts = false\ndtr_pulse = true", &no_cli()).unwrap());
+        let lines = Config::try_parse_from(args)
+            .unwrap()
+            .serial_settings()
+            .lines;
+        assert!(lines.dtr && !lines.rts && lines.dtr_pulse);
+
+        let config = Config::try_parse_from(["energymon", "--dtr", "false"]).unwrap();
+        assert!(!config.serial_settings().lines.dtr);
+    }
+
+    #[test]
+    fn probe_timeout_is_separate() {
+        let config = Config::parse_from(["energymon", "--timeout-secs", "2"]);
+        let settings = config.serial_settings();
+        assert_eq!(settings.probe_timeout, DEFAULT_PROBE_TIMEOUT);
+
+        let config = Config::parse_from(["energymon", "--probe-timeout-secs", "15"]);
+        assert_eq!(
+            config.serial_settings().probe_timeout,
+            Duration::from_secs(15)
+        );
+        assert_eq!(config.timeout_secs, 10);
+        assert!(Config::try_parse_from(["energymon", "--probe-timeout-secs", "0"]).is_err());
+    }
+
+    #[test]
+    fn verbosity() {
+        let config = Config::parse_from(["energymon"]);
+        assert_eq!(config.log_level(), None);
+        let config = Config::parse_from(["energymon", "-vv"]);
+        assert_eq!(config.log_level(), Some(log::LevelFilter::Debug));
+
+        let args = file_args("verbose = 3", &no_cli()).unwrap();
+        assert_eq!(args, ["--verbose", "--verbose", "--verbose"]);
+    }
+
+    #[test]
+    fn keepalive_should_exceed_interval() {
+        let config = |args: &[&str]| Config::parse_from([&["energymon"], args].concat());
+        assert!(config(&[]).keepalive_warning().is_none());
+        let slow = config(&["--interval-secs", "60"]);
+        assert!(slow.keepalive_warning().is_some());
+        assert!(slow.validate().is_ok());
+        assert!(
+            config(&["--interval-secs", "60", "--mqtt-keepalive-secs", "90"])
+                .keepalive_warning()
+                .is_none()
+        );
+        // A heartbeat publishes often enough on its own
+        assert!(
+            config(&["--interval-secs", "300", "--heartbeat-secs", "30"])
+                .keepalive_warning()
+                .is_none()
+        );
+        assert!(config(&["--interval-secs", "300", "--dry-run"])
+            .keepalive_warning()
+            .is_none());
+    }
+
+    #[test]
+    fn delta_only_not_retained() {
+        let config = |args: &[&str]| Config::parse_from([&["energymon"], args].concat());
+        assert!(config(&["--delta-only"]).validate().is_ok());
+        assert!(config(&["--delta-only", "--mqtt-retain"])
+            .validate()
+            .is_err());
+        assert!(config(&["--delta-only", "--homeassistant-discovery"])
+            .validate()
+            .is_err());
+    }
+
+    #[test]
+    fn telegram_start_for_probing() {
+        let config = Config::parse_from(["energymon"]);
+        assert_eq!(config.serial_settings().start, '/');
+        let config = Config::parse_from(["energymon", "--telegram-start", "#"]);
+        assert_eq!(config.serial_settings().start, '#');
+        assert_eq!(config.telegram_options().start, '#');
+    }
 }
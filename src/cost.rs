@@ -0,0 +1,117 @@
+use crate::meter::MeterReading;
+
+/// Tracks tariff consumption between readings and prices the difference.
+pub struct CostTracker {
+    price_t1: f64,
+    price_t2: f64,
+    /// Tariff 1 and tariff 2 consumption (kWh) of the previous reading.
+    previous: Option<(f64, f64)>,
+    total: f64,
+}
+
+impl CostTracker {
+    /// Prices are in currency units per kWh.
+    pub fn new(price_t1: f64, price_t2: f64) -> Self {
+        Self {
+            price_t1,
+            price_t2,
+            previous: None,
+            total: 0.0,
+        }
+    }
+
+    /// Continue from a reading saved before a restart: its counters are the
+    /// previous ones and its running total carries on.
+    pub fn resume(&mut self, previous: &MeterReading) {
+        self.previous = Some((previous.consumption_t1_kwh, previous.consumption_t2_kwh));
+        self.total = previous.cost_total.unwrap_or(0.0);
+    }
+
+    /// Set `cost_since_last` and `cost_total` on the reading. The first
+    /// reading has nothing to compare against, so it costs nothing; a counter
+    /// that went down (meter reset/replacement) also counts as zero.
+    pub fn apply(&mut self, reading: &mut MeterReading) {
+        let current = (reading.consumption_t1_kwh, reading.consumption_t2_kwh);
+
+        let cost = match self.previous {
+            Some((t1, t2)) => {
+                (current.0 - t1).max(0.0) * self.price_t1
+                    + (current.1 - t2).max(0.0) * self.price_t2
+            }
+            None => 0.0,
+        };
+
+        self.previous = Some(current);
+        self.total += cost;
+        reading.cost_since_last = Some((cost * 10000.0).round() / 10000.0);
+        reading.cost_total = Some((self.total * 10000.0).round() / 10000.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reading(t1: f64, t2: f64) -> MeterReading {
+        MeterReading {
+            consumption_t1_kwh: t1,
+            consumption_t2_kwh: t2,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn first_reading_costs_nothing() {
+        let mut tracker = CostTracker::new(0.30, 0.20);
+        let mut r = reading(100.0, 200.0);
+        tracker.apply(&mut r);
+        assert_eq!(r.cost_since_last, Some(0.0));
+        assert_eq!(r.cost_total, Some(0.0));
+    }
+
+    #[test]
+    fn prices_each_tariff_delta() {
+        let mut tracker = CostTracker::new(0.30, 0.20);
+        tracker.apply(&mut reading(100.0, 200.0));
+
+        let mut r = reading(101.0, 202.0);
+        tracker.apply(&mut r);
+        assert!((r.cost_since_last.unwrap() - 0.70).abs() < 0.0001);
+
+        let mut r = reading(101.5, 202.0);
+        tracker.apply(&mut r);
+        assert!((r.cost_since_last.unwrap() - 0.15).abs() < 0.0001);
+        assert!((r.cost_total.unwrap() - 0.85).abs() < 0.0001);
+    }
+
+    #[test]
+    fn counter_reset_counts_as_zero() {
+        let mut tracker = CostTracker::new(0.30, 0.20);
+        tracker.apply(&mut reading(100.0, 200.0));
+        tracker.apply(&mut reading(101.0, 200.0));
+
+        let mut r = reading(0.5, 0.0);
+        tracker.apply(&mut r);
+        assert_eq!(r.cost_since_last, Some(0.0));
+        assert!((r.cost_total.unwrap() - 0.30).abs() < 0.0001);
+
+        // Deltas resume from the reset values
+        let mut r = reading(1.5, 0.0);
+        tracker.apply(&mut r);
+        assert!((r.cost_since_last.unwrap() - 0.30).abs() < 0.0001);
+    }
+
+    #[test]
+    fn resumes_from_saved_reading() {
+        let mut tracker = CostTracker::new(0.30, 0.20);
+        tracker.resume(&MeterReading {
+            cost_total: Some(5.0),
+            ..reading(100.0, 200.0)
+        });
+
+        let mut r = reading(101.0, 200.0);
+        tracker.apply(&mut r);
+        assert!((r.cost_since_last.unwrap() - 0.30).abs() < 0.0001);
+        assert!((r.cost_total.unwrap() - 5.30).abs() < 0.0001);
+    }
+}
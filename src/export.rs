@@ -0,0 +1,226 @@
+use anyhow::{Context, Result};
+use log::info;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::meter::MeterReading;
+use crate::mqtt::{self, PayloadFormat};
+use crate::sink::ReadingSink;
+
+/// Appends readings as CSV rows: the timestamp followed by every numeric field.
+pub struct CsvWriter {
+    path: PathBuf,
+    file: Mutex<Option<File>>,
+}
+
+impl CsvWriter {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            file: Mutex::new(None),
+        }
+    }
+
+    /// Append one reading and flush it to disk. Reopens the file (writing a
+    /// fresh header) if it was rotated or removed since the last write.
+    pub fn write(&self, reading: &MeterReading) -> Result<()> {
+        let mut file = self.file.lock().expect("CSV lock poisoned");
+        if file.as_ref().is_some_and(|f| is_rotated(f, &self.path)) {
+            info!("{} was rotated, reopening", self.path.display());
+            *file = None;
+        }
+
+        let file = match &mut *file {
+            Some(file) => file,
+            None => file.insert(open_csv(&self.path)?),
+        };
+
+        let row = csv_row(reading)?;
+        file.write_all(row.as_bytes())
+            .and_then(|_| file.flush())
+            .with_context(|| format!("Failed to write to {}", self.path.display()))?;
+        file.sync_data()
+            .with_context(|| format!("Failed to sync {}", self.path.display()))?;
+        Ok(())
+    }
+}
+
+/// Open the CSV file for appending, writing the header if the file is new or empty.
+fn open_csv(path: &Path) -> Result<File> {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+
+    if file.metadata()?.len() == 0 {
+        file.write_all(csv_header()?.as_bytes())
+            .with_context(|| format!("Failed to write header to {}", path.display()))?;
+    }
+
+    Ok(file)
+}
+
+impl ReadingSink for CsvWriter {
+    fn name(&self) -> &str {
+        "csv"
+    }
+
+    fn publish(&self, reading: &MeterReading) -> Result<()> {
+        self.write(reading)
+    }
+}
+
+/// Prints readings as pretty JSON on stdout, for --dry-run.
+pub struct StdoutSink {
+    format: PayloadFormat,
+}
+
+impl StdoutSink {
+    pub fn new(format: PayloadFormat) -> Self {
+        Self { format }
+    }
+}
+
+impl ReadingSink for StdoutSink {
+    fn name(&self) -> &str {
+        "stdout"
+    }
+
+    fn publish(&self, reading: &MeterReading) -> Result<()> {
+        println!("{:#}", mqtt::reading_json(reading, &self.format)?);
+        Ok(())
+    }
+
+    fn publish_batch(&self, readings: &[MeterReading]) -> Result<()> {
+        let values = readings
+            .iter()
+            .map(|reading| mqtt::reading_json(reading, &self.format))
+            .collect::<Result<Vec<_>>>()?;
+        println!("{:#}", serde_json::Value::Array(values));
+        Ok(())
+    }
+}
+
+/// True if the path no longer refers to the file we have open.
+fn is_rotated(file: &File, path: &Path) -> bool {
+    match (file.metadata(), fs::metadata(path)) {
+        (Ok(open), Ok(current)) => open.ino() != current.ino() || open.dev() != current.dev(),
+        _ => true,
+    }
+}
+
+/// Numeric fields of a reading in declaration order, flags as 0/1. Optional
+/// fields the meter didn't report are `None` so columns stay aligned with
+/// the header.
+fn numeric_fields(reading: &MeterReading) -> Result<Vec<(String, Option<f64>)>> {
+    let value = serde_json::to_value(reading).context("Failed to serialize reading")?;
+    Ok(value
+        .as_object()
+        .into_iter()
+        .flatten()
+        .filter_map(|(k, v)| {
+            let value = match v {
+                serde_json::Value::Number(n) => n.as_f64(),
+                serde_json::Value::Bool(b) => Some(f64::from(u8::from(*b))),
+                serde_json::Value::Null => None,
+                _ => return None,
+            };
+            Some((k.clone(), value))
+        })
+        .collect())
+}
+
+fn csv_header() -> Result<String> {
+    let mut columns = vec!["timestamp".to_string()];
+    columns.extend(
+        numeric_fields(&MeterReading::default())?
+            .into_iter()
+            .map(|(k, _)| k),
+    );
+    Ok(columns.join(",") + "\n")
+}
+
+fn csv_row(reading: &MeterReading) -> Result<String> {
+    let mut columns = vec![reading.timestamp.clone()];
+    columns.extend(
+        numeric_fields(reading)?
+            .into_iter()
+            .map(|(_, v)| v.map(|v| v.to_string()).unwrap_or_default()),
+    );
+    Ok(columns.join(",") + "\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("energymon-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir.join("readings.csv")
+    }
+
+    fn reading(kwh: f64) -> MeterReading {
+        MeterReading {
+            consumption_total_kwh: kwh,
+            timestamp: "2026-02-27 17:26:26.675439".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn header_written_once() {
+        let path = temp_path("header");
+        let writer = CsvWriter::new(&path);
+        writer.write(&reading(1.5)).unwrap();
+        writer.write(&reading(2.5)).unwrap();
+
+        let contents = fs::read_to_string(&path).unwrap();
+        let lines: Vec<_> = contents.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("timestamp,consumption_total_kwh,"));
+        assert!(lines[1].starts_with("2026-02-27 17:26:26.675439,1.5,"));
+        assert!(lines[2].starts_with("2026-02-27 17:26:26.675439,2.5,"));
+        assert_eq!(lines[0].split(',').count(), lines[1].split(',').count());
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn reopens_after_rotation() {
+        let path = temp_path("rotate");
+        let writer = CsvWriter::new(&path);
+        writer.write(&reading(1.0)).unwrap();
+
+        fs::rename(&path, path.with_extension("csv.1")).unwrap();
+        writer.write(&reading(2.0)).unwrap();
+
+        let contents = fs::read_to_string(&path).unwrap();
+        let lines: Vec<_> = contents.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("timestamp,"));
+        assert!(lines[1].contains(",2,"));
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn stale_row_matches_header() {
+        let reading = MeterReading {
+            stale: Some(true),
+            gas_total_m3: Some(1234.5),
+            ..reading(1.5)
+        };
+        let header = csv_header().unwrap();
+        let row = csv_row(&reading).unwrap();
+        let columns: Vec<_> = header.trim_end().split(',').collect();
+        let values: Vec<_> = row.trim_end().split(',').collect();
+        assert_eq!(columns.len(), values.len());
+
+        let value = |name| values[columns.iter().position(|c| *c == name).unwrap()];
+        assert_eq!(value("stale"), "1");
+        assert_eq!(value("gas_total_m3"), "1234.5");
+    }
+}
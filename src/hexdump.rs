@@ -0,0 +1,120 @@
+use log::info;
+use std::io::{self, BufRead, Read};
+
+/// Reader adapter that logs every chunk passing through it as a classic
+/// offset/hex/ASCII dump, for reverse-engineering unsupported meters.
+/// Passes data through untouched when disabled.
+pub struct HexDump<R> {
+    inner: R,
+    enabled: bool,
+    offset: usize,
+}
+
+impl<R> HexDump<R> {
+    pub fn new(inner: R, enabled: bool) -> Self {
+        Self {
+            inner,
+            enabled,
+            offset: 0,
+        }
+    }
+
+    fn dump(&mut self, bytes: &[u8]) {
+        if self.enabled && !bytes.is_empty() {
+            for line in format_dump(self.offset, bytes) {
+                info!("{}", line);
+            }
+        }
+        self.offset += bytes.len();
+    }
+}
+
+impl<R: Read> Read for HexDump<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.dump(&buf[..n]);
+        Ok(n)
+    }
+}
+
+impl<R: BufRead> BufRead for HexDump<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    /// Bytes are dumped once they are consumed, so peeking doesn't dump
+    /// them twice.
+    fn consume(&mut self, amt: usize) {
+        if self.enabled {
+            let consumed = self
+                .inner
+                .fill_buf()
+                .map(|buf| buf[..amt.min(buf.len())].to_vec())
+                .unwrap_or_default();
+            self.dump(&consumed);
+        } else {
+            self.offset += amt;
+        }
+        self.inner.consume(amt);
+    }
+}
+
+/// Format `bytes` as 16-byte rows of offset, hex and printable ASCII, with
+/// control characters such as STX, CR and LF shown as `.`.
+fn format_dump(offset: usize, bytes: &[u8]) -> Vec<String> {
+    bytes
+        .chunks(16)
+        .enumerate()
+        .map(|(row, chunk)| {
+            let hex: Vec<String> = (0..16)
+                .map(|i| match chunk.get(i) {
+                    Some(b) => format!("{:02x}", b),
+                    None => "  ".to_string(),
+                })
+                .collect();
+            let ascii: String = chunk
+                .iter()
+                .map(|&b| {
+                    if b.is_ascii_graphic() || b == b' ' {
+                        b as char
+                    } else {
+                        '.'
+                    }
+                })
+                .collect();
+            format!(
+                "{:08x}  {}  {}  |{}|",
+                offset + row * 16,
+                hex[..8].join(" "),
+                hex[8..].join(" "),
+                ascii
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dump_rows() {
+        let lines = format_dump(0x10, b"\x02/ISk5MT174-0001\r\n!");
+        assert_eq!(
+            lines,
+            [
+                "00000010  02 2f 49 53 6b 35 4d 54  31 37 34 2d 30 30 30 31  |./ISk5MT174-0001|",
+                "00000020  0d 0a 21                                          |..!|",
+            ]
+        );
+    }
+
+    #[test]
+    fn passes_data_through() {
+        let mut reader = HexDump::new(&b"/ISk5MT174\r\n1-0:1.8.0(1*kWh)\r\n"[..], true);
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        assert_eq!(line, "/ISk5MT174\r\n");
+        assert_eq!(reader.offset, 12);
+    }
+}
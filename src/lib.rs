@@ -0,0 +1,17 @@
+//! Reading IEC 62056-21 and DSMR smart meters through an optical or P1 port.
+//!
+//! The `energymon` binary publishes readings to MQTT; this library exposes
+//! the parts it is built on so other front-ends can reuse them:
+//! [`protocol::MeterConnection`] opens a meter (serial, file, TCP) and reads
+//! telegrams, [`protocol::parse_obis_line`] parses single data lines and
+//! [`meter::MeterReading`] holds the result. Implement [`sink::ReadingSink`]
+//! to hand readings to a destination of your own.
+
+mod hexdump;
+pub mod meter;
+mod mock;
+pub mod obis;
+pub mod probe;
+pub mod profile;
+pub mod protocol;
+pub mod sink;
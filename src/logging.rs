@@ -0,0 +1,60 @@
+use chrono::{SecondsFormat, Utc};
+use log::{LevelFilter, Record};
+use std::io::Write;
+
+/// Log line format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum LogFormat {
+    /// env_logger's human-readable lines.
+    #[default]
+    Text,
+    /// One JSON object per line, for log shippers like Loki.
+    Json,
+}
+
+/// Install the global logger. `level` comes from -v; RUST_LOG, if set,
+/// takes precedence.
+pub fn init(level: Option<LevelFilter>, format: LogFormat) {
+    let mut logger = env_logger::Builder::new();
+    if let Some(level) = level {
+        logger.filter_level(level);
+    }
+    // Parsed last so RUST_LOG overrides -v
+    logger.parse_default_env();
+
+    if format == LogFormat::Json {
+        logger.format(|buf, record| writeln!(buf, "{}", json_line(record)));
+    }
+    logger.init();
+}
+
+/// Render a log record as a single-line JSON object.
+fn json_line(record: &Record) -> serde_json::Value {
+    serde_json::json!({
+        "timestamp": Utc::now().to_rfc3339_opts(SecondsFormat::Micros, true),
+        "level": record.level().as_str(),
+        "target": record.target(),
+        "message": record.args().to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_line_fields() {
+        let line = json_line(
+            &Record::builder()
+                .level(log::Level::Warn)
+                .target("energymon::protocol")
+                .args(format_args!("Telegram BCC mismatch"))
+                .build(),
+        );
+        assert_eq!(line["level"], "WARN");
+        assert_eq!(line["target"], "energymon::protocol");
+        assert_eq!(line["message"], "Telegram BCC mismatch");
+        assert!(line["timestamp"].as_str().unwrap().ends_with('Z'));
+        assert!(!line.to_string().contains('\n'));
+    }
+}
@@ -1,46 +1,414 @@
+mod average;
+mod breaker;
 mod config;
-mod meter;
+mod cost;
+mod export;
+mod logging;
 mod mqtt;
-mod probe;
-mod protocol;
+mod pipe;
+mod prometheus;
+mod retry;
+mod socket;
+mod sqlite;
+mod stale;
+mod state;
 
-use anyhow::Result;
-use clap::Parser;
-use log::{error, info};
-use std::time::Duration;
+// The meter side lives in the library; importing it here keeps the
+// `crate::meter` style paths of the binary's modules working
+use energymon::{meter, obis, probe, profile, protocol, sink};
+
+use anyhow::{bail, Context, Result};
+use log::{debug, error, info, warn};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Destinations shared by every reader thread.
+struct Outputs {
+    publisher: Option<Arc<mqtt::MqttPublisher>>,
+    metrics: Arc<Mutex<prometheus::MetricsState>>,
+    sinks: Vec<Box<dyn sink::ReadingSink>>,
+}
 
 fn main() -> Result<()> {
-    env_logger::init();
+    let config = config::load()?;
 
-    let config = config::Config::parse();
-    info!("Starting energymon");
+    logging::init(config.log_level(), config.log_format);
+    if let Some(warning) = config.keepalive_warning() {
+        warn!("{}", warning);
+    }
 
-    let mut conn = match &config.port {
-        Some(path) => {
-            info!("Using specified port: {}", path);
-            protocol::MeterConnection::open(
-                path,
-                &config.device_id,
-                Duration::from_secs(config.timeout_secs),
-            )?
-        }
-        None => {
-            info!("No port specified, probing for {} ...", config.device_id);
-            let result = probe::find_meter_port(&config.device_id)?;
-            protocol::MeterConnection::from_probe(result.port, &result.device_id)
+    if config.list_ports {
+        for line in probe::list_ports(&config.port_pattern)? {
+            println!("{}", line);
         }
+        return Ok(());
+    }
+    info!("Starting energymon");
+
+    // In dry-run mode no MQTT client is created at all, so no broker is needed
+    let publisher = (!config.dry_run).then(|| Arc::new(mqtt::MqttPublisher::new(&config)));
+    let metrics = Arc::new(Mutex::new(prometheus::MetricsState::default()));
+    if let Some(addr) = config.prometheus_listen {
+        prometheus::serve(addr, Arc::clone(&metrics))?;
+    }
+    match (&publisher, config.heartbeat_secs) {
+        (Some(publisher), Some(secs)) => mqtt::spawn_heartbeat(
+            Arc::clone(publisher),
+            Duration::from_secs(secs),
+            Arc::clone(&metrics),
+        ),
+        (None, Some(_)) => warn!("--heartbeat-secs has no effect without MQTT"),
+        _ => {}
+    }
+    // Ctrl-C and SIGTERM end the read loop; report how it went first
+    let summary_metrics = Arc::clone(&metrics);
+    ctrlc::set_handler(move || {
+        print_summary(&summary_metrics);
+        std::process::exit(0);
+    })
+    .context("Failed to install signal handler")?;
+
+    let mut sinks: Vec<Box<dyn sink::ReadingSink>> = Vec::new();
+    if let Some(path) = &config.csv_path {
+        sinks.push(Box::new(export::CsvWriter::new(path)));
+    }
+    if let Some(path) = &config.sqlite_path {
+        sinks.push(Box::new(sqlite::SqliteWriter::open(path)?));
+    }
+    if let Some(path) = &config.unix_socket {
+        sinks.push(Box::new(socket::SocketServer::bind(
+            path,
+            config.payload_format(),
+        )?));
+    }
+    if let Some(path) = &config.pipe_path {
+        sinks.push(Box::new(pipe::PipeWriter::create(
+            path,
+            config.payload_format(),
+        )?));
+    }
+    match &publisher {
+        Some(publisher) => sinks.push(Box::new(breaker::CircuitBreaker::new(
+            Arc::clone(publisher),
+            config.publish_buffer_size as usize,
+            breaker::BASE_COOLDOWN,
+        ))),
+        None => sinks.push(Box::new(export::StdoutSink::new(config.payload_format()))),
+    }
+    if publisher.is_none() && config.metrics_every.is_some() {
+        warn!("--metrics-every has no effect without MQTT");
+    }
+    let outputs = Outputs {
+        publisher,
+        metrics,
+        sinks,
     };
 
+    if config.state_file.is_some() && config.port.len() > 1 {
+        bail!("--state-file only supports a single meter");
+    }
+    if config.port.len() <= 1 {
+        let conn = connect(&config, config.port.first().map(String::as_str))?;
+        let result = run(&config, conn, &outputs);
+        print_summary(&outputs.metrics);
+        return result;
+    }
+
+    // One reader per port; a meter that fails only stops its own thread
+    let failed = std::thread::scope(|scope| {
+        let readers: Vec<_> = config
+            .port
+            .iter()
+            .map(|port| {
+                let (config, outputs) = (&config, &outputs);
+                scope.spawn(move || {
+                    let result =
+                        connect(config, Some(port)).and_then(|conn| run(config, conn, outputs));
+                    if let Err(e) = &result {
+                        error!("Meter on {} failed: {:#}", port, e);
+                    }
+                    result.is_err()
+                })
+            })
+            .collect();
+        readers
+            .into_iter()
+            .map(|reader| reader.join().unwrap_or(true))
+            .filter(|&failed| failed)
+            .count()
+    });
+
+    print_summary(&outputs.metrics);
+    if failed > 0 {
+        bail!("{} of {} meter(s) failed", failed, config.port.len());
+    }
+    Ok(())
+}
+
+/// Print the read statistics to stderr, keeping stdout to readings.
+fn print_summary(metrics: &Mutex<prometheus::MetricsState>) {
+    if let Ok(metrics) = metrics.lock() {
+        eprintln!("{}", metrics.summary());
+    }
+}
+
+/// Read telegrams from one meter, publishing each reading. Runs forever
+/// unless `--once` is set, in which case it returns after the first reading.
+fn run(
+    config: &config::Config,
+    mut conn: protocol::MeterConnection,
+    outputs: &Outputs,
+) -> Result<()> {
+    let mut last_reading: Option<meter::MeterReading> = None;
+    // Without prices there is no cost to estimate, the fields stay null
+    let mut cost = (config.price_t1.is_some() || config.price_t2.is_some()).then(|| {
+        cost::CostTracker::new(
+            config.price_t1.unwrap_or(0.0),
+            config.price_t2.unwrap_or(0.0),
+        )
+    });
+    let mut rate = average::ConsumptionRate::default();
+    let mut stale = config.stale_after.map(stale::StaleDetector::new);
+    let mut saved = config.state_file.as_deref().and_then(state::load);
+    let mut average = config
+        .avg_window
+        .map(|window| average::PowerAverage::new(window as usize));
+    let interval = Duration::from_secs(config.interval_secs);
+    let watchdog = config.watchdog_secs.map(Duration::from_secs);
+    let mut last_success = Instant::now();
+    let mut burst = Vec::new();
+    // Serial port last reported for the device info
+    let mut serial_port = None;
+    let mut backoff = retry::Backoff::new(
+        Duration::from_millis(config.retry_base_ms),
+        config.max_retries,
+    );
+
     loop {
-        match conn.read(Duration::from_secs(config.interval_secs)) {
-            Ok(reading) => {
-                if let Err(e) = mqtt::publish_reading(&config, &reading) {
-                    error!("Failed to publish: {}", e);
+        let started = Instant::now();
+        let mut retry_delay = Duration::ZERO;
+
+        // Some platforms keep timing out without an error or EOF, so retries
+        // alone never notice a meter that stopped responding
+        if watchdog.is_some_and(|limit| last_success.elapsed() >= limit) {
+            warn!(
+                "Watchdog: no telegram for {}s, reconnecting",
+                last_success.elapsed().as_secs()
+            );
+            reconnect(&mut conn, &outputs.metrics);
+            last_success = Instant::now();
+        }
+
+        let result = conn.read();
+        if let (Some(publisher), Some(port)) = (&outputs.publisher, conn.serial_port()) {
+            if serial_port.as_ref() != Some(&port) {
+                publisher.report_serial_port(serial_port.as_ref(), &port);
+                serial_port = Some(port);
+            }
+        }
+        if result.is_ok() {
+            last_success = Instant::now();
+        }
+        {
+            let mut metrics = outputs.metrics.lock().expect("metrics lock poisoned");
+            metrics.last_read_ok = result.is_ok();
+            if let Ok(reading) = &result {
+                metrics.reads_total += 1;
+                metrics.malformed_lines_total += u64::from(reading.malformed_line_count);
+                let elapsed = started.elapsed();
+                metrics.last_read_time = elapsed;
+                metrics.read_time_total += elapsed;
+            }
+        }
+        match result {
+            Ok(reading)
+                if config.dedup
+                    && last_reading
+                        .as_ref()
+                        .is_some_and(|last| last.same_values(&reading)) =>
+            {
+                backoff.reset();
+                debug!("Suppressing duplicate telegram from {}", reading.device_id);
+            }
+            Ok(mut reading) => {
+                backoff.reset();
+                if config.dedup {
+                    last_reading = Some(reading.clone());
+                }
+                if let Some(previous) = saved.take() {
+                    if previous.device_id == reading.device_id {
+                        info!("Resuming from state saved at {}", previous.timestamp);
+                        if let Some(cost) = &mut cost {
+                            cost.resume(&previous);
+                        }
+                        rate.resume(&previous);
+                    } else {
+                        warn!(
+                            "Ignoring saved state of {}, the meter is {}",
+                            previous.device_id, reading.device_id
+                        );
+                    }
+                }
+                if let Some(cost) = &mut cost {
+                    cost.apply(&mut reading);
+                }
+                rate.apply(&mut reading);
+                if let Some(average) = &mut average {
+                    average.apply(&mut reading);
+                }
+                if let Some(stale) = &mut stale {
+                    stale.apply(&mut reading);
+                }
+                outputs
+                    .metrics
+                    .lock()
+                    .expect("metrics lock poisoned")
+                    .readings
+                    .insert(reading.device_id.clone(), reading.clone());
+
+                if let Some(path) = &config.state_file {
+                    if let Err(e) = state::save(path, &reading) {
+                        error!("Failed to save state: {:#}", e);
+                    }
+                }
+
+                // A burst is read without waiting for the interval and
+                // published once complete
+                burst.push(reading);
+                if burst.len() < config.burst.unwrap_or(1) as usize {
+                    continue;
+                }
+                let readings = std::mem::take(&mut burst);
+
+                // Every sink gets the reading, whichever fail; with --once the
+                // first failure becomes the exit status
+                let mut failure = None;
+                for sink in &outputs.sinks {
+                    let published = match readings.as_slice() {
+                        [reading] => sink.publish(reading),
+                        readings => sink.publish_batch(readings),
+                    };
+                    if let Err(e) = published {
+                        let e = e.context(format!("Failed to publish to {}", sink.name()));
+                        if config.once && failure.is_none() {
+                            failure = Some(e);
+                        } else {
+                            error!("{:#}", e);
+                        }
+                    }
+                }
+                if config.once {
+                    return failure.map_or(Ok(()), Err);
+                }
+                if let (Some(publisher), Some(every)) = (&outputs.publisher, config.metrics_every) {
+                    let metrics = outputs.metrics.lock().expect("metrics lock poisoned");
+                    // Released before publishing, which may wait on the broker
+                    let due = metrics.reads_total.is_multiple_of(every);
+                    let baud_rate = conn.serial_port().map(|(_, baud_rate)| baud_rate);
+                    let json = mqtt::metrics_json(&metrics, baud_rate);
+                    drop(metrics);
+                    if due {
+                        if let Err(e) = publisher.publish_metrics(&json) {
+                            error!("Failed to publish metrics: {:#}", e);
+                        }
+                    }
                 }
             }
+            Err(e) if config.once => return Err(e),
             Err(e) => {
+                outputs
+                    .metrics
+                    .lock()
+                    .expect("metrics lock poisoned")
+                    .read_errors_total += 1;
                 error!("Failed to read meter: {}", e);
+
+                let delay = backoff.next_delay();
+                if protocol::is_disconnect(&e) {
+                    warn!("Meter connection lost, reconnecting");
+                } else if delay.is_none() {
+                    warn!(
+                        "Read failed {} times in a row, reconnecting",
+                        config.max_retries + 1
+                    );
+                }
+                if delay.is_none() || protocol::is_disconnect(&e) {
+                    reconnect(&mut conn, &outputs.metrics);
+                }
+                retry_delay = delay.unwrap_or_default();
             }
         }
+
+        // Sleep for the remainder of the interval so the cadence stays stable
+        // regardless of how long the read took, backing off further after errors
+        let remaining = interval.saturating_sub(started.elapsed());
+        std::thread::sleep(remaining.max(retry_delay));
+    }
+}
+
+/// Reconnect to the meter, counting the attempt in the metrics.
+fn reconnect(conn: &mut protocol::MeterConnection, metrics: &Mutex<prometheus::MetricsState>) {
+    metrics
+        .lock()
+        .expect("metrics lock poisoned")
+        .reconnects_total += 1;
+    if let Err(e) = conn.reconnect() {
+        error!("Failed to reconnect: {}", e);
+    }
+}
+
+/// Open the telegram source selected on the command line: a capture file,
+/// a TCP stream, the given serial port, or the first probed port that
+/// answers with the expected device ID.
+fn connect(config: &config::Config, port: Option<&str>) -> Result<protocol::MeterConnection> {
+    let timeout = Duration::from_secs(config.timeout_secs);
+
+    if config.simulate {
+        return protocol::MeterConnection::simulate(&config.device_id, config.telegram_options());
+    }
+
+    match (&config.source, port) {
+        (Some(config::Source::File(path)), _) => {
+            protocol::MeterConnection::open_file(path, &config.device_id, config.telegram_options())
+        }
+        (Some(config::Source::Tcp(addr)), _) => protocol::MeterConnection::open_tcp(
+            addr,
+            &config.device_id,
+            timeout,
+            config.telegram_options(),
+        ),
+        (None, Some(path)) if config.mode == protocol::Mode::D => {
+            protocol::MeterConnection::open_mode_d(
+                path,
+                &config.device_id,
+                config.mode_d_baud,
+                timeout,
+                config.serial_settings(),
+                config.telegram_options(),
+            )
+        }
+        (None, Some(path)) => {
+            info!("Using specified port: {}", path);
+            probe::preflight(path, &config.serial_settings(), timeout)?;
+            protocol::MeterConnection::open(
+                path,
+                &config.device_id,
+                timeout,
+                config.serial_settings(),
+                config.telegram_options(),
+            )
+        }
+        (None, None) if config.mode == protocol::Mode::D => {
+            bail!("--mode d requires --port, probing only works for Mode C meters")
+        }
+        (None, None) => {
+            info!("No port specified, probing for {} ...", config.device_id);
+            let result = probe::find_meter_port(&config.device_id, &config.serial_settings())?;
+            Ok(protocol::MeterConnection::from_probe(
+                result,
+                config.serial_settings(),
+                config.telegram_options(),
+            ))
+        }
     }
 }
@@ -1,8 +1,12 @@
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
-#[derive(Debug, Serialize, Default)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct MeterReading {
     pub device_id: String,
+    /// Meter serial number, hex-decoded — OBIS 0-0:96.1.1 (empty if absent)
+    pub equipment_id: String,
     /// Positive active energy total (kWh) — OBIS 1-0:1.8.0
     pub consumption_total_kwh: f64,
     /// Positive active energy tariff 1 / HT (kWh) — OBIS 1-0:1.8.1
@@ -15,6 +19,12 @@ pub struct MeterReading {
     pub production_t1_kwh: f64,
     /// Negative active energy tariff 2 (kWh) — OBIS 1-0:2.8.2
     pub production_t2_kwh: f64,
+    /// Currently active tariff, e.g. 1 or 2 — OBIS 0-0:96.14.0
+    pub active_tariff: Option<u32>,
+    /// Positive reactive energy total (kvarh) — OBIS 1-0:3.8.0
+    pub reactive_import_total_kvarh: f64,
+    /// Negative reactive energy total (kvarh) — OBIS 1-0:4.8.0
+    pub reactive_export_total_kvarh: f64,
     /// Phase 1 voltage (V) — OBIS 1-0:32.7.0
     pub phase1_voltage: f64,
     /// Phase 2 voltage (V) — OBIS 1-0:52.7.0
@@ -27,35 +37,415 @@ pub struct MeterReading {
     pub phase2_current: f64,
     /// Phase 3 current (A) — OBIS 1-0:71.7.0
     pub phase3_current: f64,
+    /// Sum of the phase currents (A) — OBIS 1-0:90.7.0
+    pub total_current: Option<f64>,
     /// Grid frequency (Hz) — OBIS 1-0:14.7.0
     pub frequency: f64,
-    /// Phase 1 power factor — OBIS 1-0:33.7.0
+    /// Phase 1 power factor, negative when leading — OBIS 1-0:33.7.0
     pub phase1_pf: f64,
     /// Phase 2 power factor — OBIS 1-0:53.7.0
     pub phase2_pf: f64,
     /// Phase 3 power factor — OBIS 1-0:73.7.0
     pub phase3_pf: f64,
-    /// Phase 1 real power (W) — computed as V × I × PF
+    /// Power factor over all phases, negative when leading — OBIS 1-0:13.7.0
+    pub total_pf: Option<f64>,
+    /// Whether the power factor is leading (capacitive): the meter reported
+    /// a negative total PF, or a negative phase PF if it has no total
+    pub pf_leading: bool,
+    /// Phase 1 real power (W) — measured import minus export power when the
+    /// meter reports 1-0:21.7.0/22.7.0, otherwise computed as V × I × PF
     pub phase1_power: f64,
-    /// Phase 2 real power (W) — computed as V × I × PF
+    /// Phase 2 real power (W) — see `phase1_power` (1-0:41.7.0/42.7.0)
     pub phase2_power: f64,
-    /// Phase 3 real power (W) — computed as V × I × PF
+    /// Phase 3 real power (W) — see `phase1_power` (1-0:61.7.0/62.7.0)
     pub phase3_power: f64,
-    /// Total real power (W) — sum of all phases
+    /// Phase 1 instantaneous import power (kW) — OBIS 1-0:21.7.0
+    pub phase1_import_power_kw: Option<f64>,
+    /// Phase 2 instantaneous import power (kW) — OBIS 1-0:41.7.0
+    pub phase2_import_power_kw: Option<f64>,
+    /// Phase 3 instantaneous import power (kW) — OBIS 1-0:61.7.0
+    pub phase3_import_power_kw: Option<f64>,
+    /// Phase 1 instantaneous export power (kW) — OBIS 1-0:22.7.0
+    pub phase1_export_power_kw: Option<f64>,
+    /// Phase 2 instantaneous export power (kW) — OBIS 1-0:42.7.0
+    pub phase2_export_power_kw: Option<f64>,
+    /// Phase 3 instantaneous export power (kW) — OBIS 1-0:62.7.0
+    pub phase3_export_power_kw: Option<f64>,
+    /// Phase 1 instantaneous apparent power (kVA) — OBIS 1-0:29.7.0
+    pub phase1_apparent_power_kva: Option<f64>,
+    /// Phase 2 instantaneous apparent power (kVA) — OBIS 1-0:49.7.0
+    pub phase2_apparent_power_kva: Option<f64>,
+    /// Phase 3 instantaneous apparent power (kVA) — OBIS 1-0:69.7.0
+    pub phase3_apparent_power_kva: Option<f64>,
+    /// Phase 1 apparent power (VA) — the measured 1-0:29.7.0 value, else
+    /// computed as V × I
+    pub phase1_apparent_va: f64,
+    /// Phase 2 apparent power (VA) — see `phase1_apparent_va` (1-0:49.7.0)
+    pub phase2_apparent_va: f64,
+    /// Phase 3 apparent power (VA) — see `phase1_apparent_va` (1-0:69.7.0)
+    pub phase3_apparent_va: f64,
+    /// Phase 1 reactive power (var) — computed as √(S² − P²), negative when
+    /// the meter reports a negative (capacitive) power factor
+    pub phase1_reactive_var: f64,
+    /// Phase 2 reactive power (var) — see `phase1_reactive_var`
+    pub phase2_reactive_var: f64,
+    /// Phase 3 reactive power (var) — see `phase1_reactive_var`
+    pub phase3_reactive_var: f64,
+    /// Total real power (W) — measured import + export power when the meter
+    /// reports 1-0:1.7.0/2.7.0, else the combined power 1-0:15.7.0, otherwise
+    /// the sum of all phases
     pub total_power: f64,
+    /// Total apparent power (VA) — the measured 1-0:9.7.0 value, else the
+    /// sum of all phases
+    pub total_apparent_va: f64,
+    /// Total reactive power (var) — the sum of all phases
+    pub total_reactive_var: f64,
+    /// Instantaneous import power (kW) — OBIS 1-0:1.7.0
+    pub import_power_kw: Option<f64>,
+    /// Instantaneous export power (kW) — OBIS 1-0:2.7.0
+    pub export_power_kw: Option<f64>,
+    /// Combined active power of all phases (kW) — OBIS 1-0:15.7.0
+    pub combined_power_kw: Option<f64>,
+    /// Signed net active power (kW), negative when exporting — OBIS 1-0:16.7.0
+    pub net_power_kw: Option<f64>,
+    /// Instantaneous apparent power of all phases (kVA) — OBIS 1-0:9.7.0
+    pub apparent_power_kva: Option<f64>,
+    /// Net grid power (W), positive when importing and negative when exporting —
+    /// the measured 1-0:16.7.0 value, else import minus export power, or the
+    /// total power when neither is reported
+    pub net_power: f64,
+    /// Mean total power (W) over the last `--avg-window` readings
+    pub total_power_avg: Option<f64>,
+    /// Average power (W) since the previous reading, derived from the growth
+    /// of `consumption_total_kwh` (null for the first reading or after a reset)
+    pub avg_power_w: Option<f64>,
+    /// Whether the energy counters stopped advancing despite nonzero power for
+    /// `--stale-after` readings (null without the option)
+    pub stale: Option<bool>,
+    /// Estimated cost of the tariff 1/2 consumption since the previous reading
+    /// (null without --price-t1/--price-t2)
+    pub cost_since_last: Option<f64>,
+    /// Running estimated cost since energymon started (null without
+    /// --price-t1/--price-t2)
+    pub cost_total: Option<f64>,
     pub timestamp: String,
+    /// Message from the utility to the consumer, hex-decoded — OBIS
+    /// 0-0:96.13.0 (empty if absent)
+    pub text_message: String,
+    /// Meter clock as ISO-8601 with UTC offset — OBIS 0-0:1.0.0 (empty if absent or malformed)
+    pub meter_timestamp: String,
+    /// Gas meter total volume (m³) — OBIS 0-n:24.2.1 on M-Bus channel n
+    pub gas_total_m3: Option<f64>,
+    /// Capture time of the gas reading as ISO-8601 with UTC offset (empty if absent)
+    pub gas_timestamp: String,
+    /// Voltage sags on L1 — OBIS 1-0:32.32.0
+    pub phase1_voltage_sags: Option<u32>,
+    /// Voltage sags on L2 — OBIS 1-0:52.32.0
+    pub phase2_voltage_sags: Option<u32>,
+    /// Voltage sags on L3 — OBIS 1-0:72.32.0
+    pub phase3_voltage_sags: Option<u32>,
+    /// Voltage swells on L1 — OBIS 1-0:32.36.0
+    pub phase1_voltage_swells: Option<u32>,
+    /// Voltage swells on L2 — OBIS 1-0:52.36.0
+    pub phase2_voltage_swells: Option<u32>,
+    /// Voltage swells on L3 — OBIS 1-0:72.36.0
+    pub phase3_voltage_swells: Option<u32>,
+    /// Power failures in any phase — OBIS 0-0:96.7.21
+    pub power_failures: Option<u32>,
+    /// Numeric values of OBIS codes energymon has no field for, keyed by code
+    pub other_obis: BTreeMap<String, ObisValue>,
+    /// Lines in the telegram that were not valid OBIS data lines, a sign of a
+    /// degraded optical link
+    pub malformed_line_count: u32,
+    /// Checksum after the `!` of the telegram as received, e.g. the DSMR
+    /// CRC16 in hex (empty if absent)
+    pub telegram_crc: String,
 }
 
 impl MeterReading {
-    /// Calculate per-phase and total real power from voltage, current, and power factor.
-    pub fn calculate_power(&mut self) {
-        self.phase1_power =
-            (self.phase1_voltage * self.phase1_current * self.phase1_pf * 100.0).round() / 100.0;
-        self.phase2_power =
-            (self.phase2_voltage * self.phase2_current * self.phase2_pf * 100.0).round() / 100.0;
-        self.phase3_power =
-            (self.phase3_voltage * self.phase3_current * self.phase3_pf * 100.0).round() / 100.0;
-        self.total_power =
-            (((self.phase1_power + self.phase2_power + self.phase3_power) * 100.0).round()) / 100.0;
+    /// Whether two readings carry identical values, ignoring the host timestamp.
+    pub fn same_values(&self, other: &Self) -> bool {
+        let strip = |r: &Self| Self {
+            timestamp: String::new(),
+            ..r.clone()
+        };
+        strip(self) == strip(other)
+    }
+
+    /// Calculate per-phase and total real, apparent and reactive power from
+    /// voltage, current, and power factor. The total real power prefers the
+    /// meter's measured instantaneous power when available.
+    ///
+    /// Phases below `settings.min_phase_voltage` are treated as unconnected
+    /// and get zero power, whatever stale current the meter still reports.
+    pub fn calculate_power(&mut self, settings: &PowerSettings) {
+        let round = |value: f64| settings.round(value);
+        let current = |voltage: f64, current: f64| {
+            if voltage < settings.min_phase_voltage {
+                0.0
+            } else {
+                current
+            }
+        };
+        let i1 = current(self.phase1_voltage, self.phase1_current);
+        let i2 = current(self.phase2_voltage, self.phase2_current);
+        let i3 = current(self.phase3_voltage, self.phase3_current);
+
+        // Measured per-phase power is far more accurate than V × I × PF
+        let phase_power = |import: Option<f64>, export: Option<f64>, estimate: f64| {
+            if import.is_none() && export.is_none() {
+                return round(estimate);
+            }
+            round((import.unwrap_or(0.0) - export.unwrap_or(0.0)) * 1000.0)
+        };
+        self.phase1_power = phase_power(
+            self.phase1_import_power_kw,
+            self.phase1_export_power_kw,
+            self.phase1_voltage * i1 * self.phase1_pf.abs(),
+        );
+        self.phase2_power = phase_power(
+            self.phase2_import_power_kw,
+            self.phase2_export_power_kw,
+            self.phase2_voltage * i2 * self.phase2_pf.abs(),
+        );
+        self.phase3_power = phase_power(
+            self.phase3_import_power_kw,
+            self.phase3_export_power_kw,
+            self.phase3_voltage * i3 * self.phase3_pf.abs(),
+        );
+        let estimated_total = round(self.phase1_power + self.phase2_power + self.phase3_power);
+
+        // A negative PF only marks a leading load, the power itself is positive
+        self.pf_leading = match self.total_pf {
+            Some(pf) => pf < 0.0,
+            None => [self.phase1_pf, self.phase2_pf, self.phase3_pf]
+                .iter()
+                .any(|&pf| pf < 0.0),
+        };
+
+        let (s1, q1) = apparent_reactive(
+            self.phase1_voltage,
+            i1,
+            self.phase1_pf,
+            self.phase1_apparent_power_kva
+                .map(|kva| (kva, self.phase1_power)),
+        );
+        let (s2, q2) = apparent_reactive(
+            self.phase2_voltage,
+            i2,
+            self.phase2_pf,
+            self.phase2_apparent_power_kva
+                .map(|kva| (kva, self.phase2_power)),
+        );
+        let (s3, q3) = apparent_reactive(
+            self.phase3_voltage,
+            i3,
+            self.phase3_pf,
+            self.phase3_apparent_power_kva
+                .map(|kva| (kva, self.phase3_power)),
+        );
+        self.phase1_apparent_va = round(s1);
+        self.phase2_apparent_va = round(s2);
+        self.phase3_apparent_va = round(s3);
+        self.phase1_reactive_var = round(q1);
+        self.phase2_reactive_var = round(q2);
+        self.phase3_reactive_var = round(q3);
+        self.total_apparent_va = self
+            .apparent_power_kva
+            .map_or(round(s1 + s2 + s3), |kva| round(kva * 1000.0));
+        self.total_reactive_var = round(q1 + q2 + q3);
+
+        match (self.import_power_kw, self.export_power_kw) {
+            (None, None) => {
+                // The combined power has no direction, like the estimate
+                let total = self
+                    .combined_power_kw
+                    .map_or(estimated_total, |kw| round(kw * 1000.0));
+                self.total_power = total;
+                self.net_power = total;
+            }
+            (import, export) => {
+                let import_w = import.unwrap_or(0.0) * 1000.0;
+                let export_w = export.unwrap_or(0.0) * 1000.0;
+                self.total_power = round(import_w + export_w);
+                self.net_power = round(import_w - export_w);
+            }
+        }
+        if let Some(kw) = self.net_power_kw {
+            self.net_power = round(kw * 1000.0);
+        }
+    }
+}
+
+/// Value of an OBIS code without a dedicated field, with the unit the meter
+/// sent after the `*` (empty if it sent none).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+pub struct ObisValue {
+    pub value: f64,
+    pub unit: String,
+}
+
+/// How calculated power values are derived and rounded.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PowerSettings {
+    /// Phases below this voltage count as unconnected and report no power.
+    pub min_phase_voltage: f64,
+    /// Decimals to round power values to; 0 gives whole watts, negative
+    /// values round to tens, hundreds, ...
+    pub precision: i32,
+}
+
+impl Default for PowerSettings {
+    fn default() -> Self {
+        Self {
+            min_phase_voltage: 0.0,
+            precision: DEFAULT_POWER_PRECISION,
+        }
+    }
+}
+
+/// Decimals calculated power values are rounded to by default.
+pub const DEFAULT_POWER_PRECISION: i32 = 2;
+
+impl PowerSettings {
+    fn round(&self, value: f64) -> f64 {
+        let factor = 10f64.powi(self.precision);
+        (value * factor).round() / factor
+    }
+}
+
+/// Apparent power S and reactive power Q = √(S² − P²) for one phase,
+/// unrounded. S is the `measured` apparent power (kVA) with the phase's real
+/// power (W) when the meter reports it, else V × I with P = S × |PF|. Q takes
+/// the sign of the power factor.
+fn apparent_reactive(
+    voltage: f64,
+    current: f64,
+    pf: f64,
+    measured: Option<(f64, f64)>,
+) -> (f64, f64) {
+    let (apparent, real) = match measured {
+        Some((kva, power)) => (kva * 1000.0, power.abs()),
+        None => (voltage * current, voltage * current * pf.abs()),
+    };
+    let reactive = (apparent * apparent - real * real)
+        .max(0.0)
+        .sqrt()
+        .copysign(pf);
+    (apparent, reactive)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_values_ignores_timestamp() {
+        let a = MeterReading {
+            consumption_total_kwh: 2686.675,
+            timestamp: "2026-02-27 17:26:26.675439".to_string(),
+            ..Default::default()
+        };
+        let b = MeterReading {
+            timestamp: "2026-02-27 17:26:27.001122".to_string(),
+            ..a.clone()
+        };
+        assert!(a.same_values(&b));
+
+        let c = MeterReading {
+            consumption_total_kwh: 2686.676,
+            ..b.clone()
+        };
+        assert!(!a.same_values(&c));
+    }
+
+    #[test]
+    fn apparent_and_reactive_power() {
+        let mut r = MeterReading {
+            phase1_voltage: 230.0,
+            phase1_current: 10.0,
+            phase1_pf: 0.8,
+            phase2_voltage: 230.0,
+            phase2_current: 10.0,
+            phase2_pf: -0.6,
+            ..Default::default()
+        };
+        r.calculate_power(&PowerSettings::default());
+        assert_eq!(r.phase1_apparent_va, 2300.0);
+        assert_eq!(r.phase1_reactive_var, 1380.0);
+        assert_eq!(r.phase2_reactive_var, -1840.0);
+        assert_eq!(r.phase3_reactive_var, 0.0);
+        assert_eq!(r.total_apparent_va, 4600.0);
+        assert_eq!(r.total_reactive_var, -460.0);
+    }
+
+    #[test]
+    fn phases_below_min_voltage_report_no_power() {
+        let mut r = MeterReading {
+            phase1_voltage: 231.0,
+            phase1_current: 2.0,
+            phase1_pf: 1.0,
+            phase2_voltage: 3.5,
+            phase2_current: 1.2,
+            phase2_pf: 1.0,
+            ..Default::default()
+        };
+        r.calculate_power(&PowerSettings {
+            min_phase_voltage: 50.0,
+            ..Default::default()
+        });
+        assert_eq!(r.phase1_power, 462.0);
+        assert_eq!(r.phase2_power, 0.0);
+        assert_eq!(r.phase2_apparent_va, 0.0);
+        assert_eq!(r.total_power, 462.0);
+
+        r.calculate_power(&PowerSettings::default());
+        assert_eq!(r.phase2_power, 4.2);
+    }
+
+    #[test]
+    fn power_precision() {
+        let mut r = MeterReading {
+            phase1_voltage: 231.3,
+            phase1_current: 0.987,
+            phase1_pf: 0.995,
+            ..Default::default()
+        };
+        r.calculate_power(&PowerSettings::default());
+        assert_eq!(r.phase1_power, 227.15);
+
+        let whole_watts = PowerSettings {
+            precision: 0,
+            ..Default::default()
+        };
+        r.calculate_power(&whole_watts);
+        assert_eq!(r.phase1_power, 227.0);
+        assert_eq!(r.total_power, 227.0);
+
+        let tens = PowerSettings {
+            precision: -1,
+            ..Default::default()
+        };
+        r.calculate_power(&tens);
+        assert_eq!(r.phase1_power, 230.0);
+        assert_eq!(r.phase1_apparent_va, 230.0);
+    }
+
+    #[test]
+    fn measured_apparent_power() {
+        let mut r = MeterReading {
+            phase1_voltage: 230.0,
+            phase1_current: 2.0,
+            phase1_pf: -0.9,
+            phase1_import_power_kw: Some(0.3),
+            phase1_apparent_power_kva: Some(0.5),
+            apparent_power_kva: Some(0.412),
+            ..Default::default()
+        };
+        r.calculate_power(&PowerSettings::default());
+        assert_eq!(r.phase1_apparent_va, 500.0);
+        // √(500² − 300²), negative for the capacitive PF
+        assert_eq!(r.phase1_reactive_var, -400.0);
+        assert_eq!(r.total_apparent_va, 412.0);
     }
 }
@@ -0,0 +1,138 @@
+use chrono::{Local, Timelike};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::meter::{MeterReading, PowerSettings};
+
+/// A simulated three-phase meter for development without hardware.
+///
+/// Each read draws new instantaneous values around a slowly drifting load
+/// and advances the energy registers by the power consumed since the
+/// previous read, so totals grow at a realistic rate for any poll interval.
+pub struct MockMeter {
+    device_id: String,
+    rng: u64,
+    /// Mean load per phase (W), drifting a little on every read.
+    base_load: [f64; 3],
+    consumption_t1_kwh: f64,
+    consumption_t2_kwh: f64,
+    last_read: Instant,
+}
+
+impl MockMeter {
+    pub fn new(device_id: &str) -> Self {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_nanos() as u64);
+        Self {
+            device_id: format!("{}-SIM", device_id),
+            // xorshift must not start at zero
+            rng: seed | 1,
+            base_load: [250.0, 120.0, 180.0],
+            consumption_t1_kwh: 1843.210,
+            consumption_t2_kwh: 843.465,
+            last_read: Instant::now(),
+        }
+    }
+
+    /// Produce the next reading, advancing the totals by the wall-clock time
+    /// since the previous one.
+    pub fn read(&mut self, power: &PowerSettings) -> MeterReading {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_read);
+        self.last_read = now;
+        self.advance(elapsed, power)
+    }
+
+    /// Produce a reading as if `elapsed` had passed since the previous one.
+    fn advance(&mut self, elapsed: Duration, power: &PowerSettings) -> MeterReading {
+        let mut reading = MeterReading {
+            device_id: self.device_id.clone(),
+            frequency: round(50.0 + self.uniform(-0.05, 0.05), 2),
+            ..Default::default()
+        };
+
+        let mut load_w = 0.0;
+        for phase in 0..3 {
+            self.base_load[phase] =
+                (self.base_load[phase] + self.uniform(-20.0, 20.0)).clamp(50.0, 2000.0);
+            let power = self.base_load[phase] * self.uniform(0.8, 1.2);
+            let voltage = round(230.0 + self.uniform(-3.0, 3.0), 1);
+            let pf = round(self.uniform(0.90, 1.0), 3);
+            let current = round(power / (voltage * pf), 2);
+            load_w += power;
+
+            match phase {
+                0 => {
+                    (
+                        reading.phase1_voltage,
+                        reading.phase1_current,
+                        reading.phase1_pf,
+                    ) = (voltage, current, pf)
+                }
+                1 => {
+                    (
+                        reading.phase2_voltage,
+                        reading.phase2_current,
+                        reading.phase2_pf,
+                    ) = (voltage, current, pf)
+                }
+                _ => {
+                    (
+                        reading.phase3_voltage,
+                        reading.phase3_current,
+                        reading.phase3_pf,
+                    ) = (voltage, current, pf)
+                }
+            }
+        }
+
+        // Night tariff between 23:00 and 07:00, like most dual-tariff meters
+        let kwh = load_w / 1000.0 * elapsed.as_secs_f64() / 3600.0;
+        if !(7..23).contains(&Local::now().hour()) {
+            self.consumption_t2_kwh += kwh;
+        } else {
+            self.consumption_t1_kwh += kwh;
+        }
+
+        reading.consumption_t1_kwh = round(self.consumption_t1_kwh, 3);
+        reading.consumption_t2_kwh = round(self.consumption_t2_kwh, 3);
+        reading.consumption_total_kwh = round(self.consumption_t1_kwh + self.consumption_t2_kwh, 3);
+        reading.meter_timestamp = Local::now().format("%Y-%m-%dT%H:%M:%S%:z").to_string();
+
+        reading.calculate_power(power);
+        reading.timestamp = Local::now().format("%Y-%m-%d %H:%M:%S%.6f").to_string();
+        reading
+    }
+
+    /// Uniformly distributed value in `[low, high)` from a xorshift64 generator.
+    fn uniform(&mut self, low: f64, high: f64) -> f64 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        low + (self.rng >> 11) as f64 / (1u64 << 53) as f64 * (high - low)
+    }
+}
+
+fn round(value: f64, decimals: i32) -> f64 {
+    let factor = 10f64.powi(decimals);
+    (value * factor).round() / factor
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn totals_advance_with_elapsed_time() {
+        let mut meter = MockMeter::new("ISk5MT174");
+        let first = meter.advance(Duration::ZERO, &PowerSettings::default());
+        assert_eq!(first.device_id, "ISk5MT174-SIM");
+        assert!(first.total_power > 0.0);
+
+        let second = meter.advance(Duration::from_secs(3600), &PowerSettings::default());
+        let consumed = second.consumption_total_kwh - first.consumption_total_kwh;
+        // 3 phases between 50 W × 0.8 and 2000 W × 1.2 for one hour
+        assert!(consumed > 0.12 && consumed < 7.2, "consumed {}", consumed);
+        assert!((second.phase1_voltage - 230.0).abs() <= 3.0);
+    }
+}
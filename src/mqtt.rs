@@ -1,46 +1,1329 @@
 use anyhow::{Context, Result};
-use log::info;
-use rumqttc::{Client, MqttOptions, QoS};
-use std::time::Duration;
+use log::{error, info, warn};
+use rumqttc::{
+    Client, ConnectReturnCode, Connection, ConnectionError, Event, Incoming, LastWill, MqttOptions,
+    Outgoing, QoS, RecvTimeoutError,
+};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 use crate::config::Config;
 use crate::meter::MeterReading;
+use crate::prometheus::MetricsState;
+use crate::sink::ReadingSink;
 
-/// Publish a meter reading as JSON to the configured MQTT broker.
-/// Uses QoS 0 (fire-and-forget), matching the Python script's behavior.
-pub fn publish_reading(config: &Config, reading: &MeterReading) -> Result<()> {
-    let payload = serde_json::to_string(reading).context("Failed to serialize reading to JSON")?;
+/// Long-lived MQTT connections, one per broker, that are reused for every
+/// reading. Each reading is published to every broker.
+///
+/// The connections are only driven while publishing, so keep-alive pings are
+/// sent as part of each publish. If a broker drops the connection, the
+/// next publish transparently reconnects.
+pub struct MqttPublisher {
+    brokers: Vec<Broker>,
+    /// The configured topic with the placeholders that don't depend on the
+    /// reading filled in, for the status topic.
+    topic: String,
+    /// Template the state and per-field topics are rendered from.
+    topic_template: String,
+    client_id: String,
+    format: PayloadFormat,
+    split_topics: bool,
+    lwt_topic: String,
+    /// Retained device info published after every (re)connect, see
+    /// `info_json`.
+    info: serde_json::Value,
+    /// Baud rate of every serial port a meter is read from, by path.
+    serial_ports: Mutex<BTreeMap<String, u32>>,
+    /// Publish Home Assistant discovery for each meter before its first reading.
+    discovery: bool,
+    /// Device IDs whose discovery configs were published.
+    discovered: Mutex<HashSet<String>>,
+    /// Only publish the fields that changed since the meter's last payload.
+    delta_only: bool,
+    /// Last full payload published per device ID, for `delta_only`.
+    published: Mutex<HashMap<String, serde_json::Value>>,
+    qos: QoS,
+    retain: bool,
+    publish_timeout: Duration,
+}
+
+/// The client and event loop for one broker.
+struct Broker {
+    client: Client,
+    connection: Mutex<Connection>,
+    host: String,
+    port: u16,
+}
+
+/// Version of the JSON reading payload. Bump whenever a field is added,
+/// removed or renamed so consumers can dispatch on it.
+pub const SCHEMA_VERSION: u32 = 18;
+
+/// The JSON payload of a reading: the reading's fields, tagged with a type
+/// marker and the schema version.
+#[derive(Serialize)]
+pub struct Payload<'a> {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    schema_version: u32,
+    #[serde(flatten)]
+    reading: &'a MeterReading,
+}
+
+impl<'a> Payload<'a> {
+    pub fn new(reading: &'a MeterReading) -> Self {
+        Self {
+            kind: "energymon.reading",
+            schema_version: SCHEMA_VERSION,
+            reading,
+        }
+    }
+}
+
+/// Key naming used for the JSON payload.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum JsonCase {
+    /// `consumption_total_kwh`, matching the struct fields.
+    #[default]
+    Snake,
+    /// `consumptionTotalKwh`
+    Camel,
+}
+
+impl JsonCase {
+    /// Convert a snake_case field name to this case.
+    pub fn apply(self, key: &str) -> String {
+        match self {
+            JsonCase::Snake => key.to_string(),
+            JsonCase::Camel => {
+                let mut out = String::with_capacity(key.len());
+                let mut upper = false;
+                for c in key.chars() {
+                    if c == '_' {
+                        upper = true;
+                    } else if upper {
+                        out.extend(c.to_uppercase());
+                        upper = false;
+                    } else {
+                        out.push(c);
+                    }
+                }
+                out
+            }
+        }
+    }
+}
+
+/// Serialization of the reading published to the state topic.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum Encoding {
+    /// JSON text.
+    #[default]
+    Json,
+    /// MessagePack, a compact binary map with the same keys as the JSON.
+    Msgpack,
+}
+
+/// Which reading fields a payload carries and how it is encoded.
+#[derive(Debug, Clone, Default)]
+pub struct PayloadFormat {
+    pub encoding: Encoding,
+    pub case: JsonCase,
+    /// Only publish these fields (by their snake_case name). Empty keeps all.
+    pub include_fields: Vec<String>,
+    /// Never publish these fields.
+    pub exclude_fields: Vec<String>,
+    /// Flatten nested values into dot-joined keys, e.g. `other_obis.1-0:5.8.0.value`.
+    pub flat: bool,
+    /// Add a `units` object with the unit of every field that has one.
+    pub units: bool,
+}
+
+/// Fields kept regardless of the field filter, so payloads stay identifiable.
+const ALWAYS_KEPT: [&str; 4] = ["type", "schema_version", "device_id", "timestamp"];
+
+impl PayloadFormat {
+    /// Whether the field filter keeps a snake_case field.
+    fn keeps(&self, field: &str) -> bool {
+        ALWAYS_KEPT.contains(&field)
+            || ((self.include_fields.is_empty() || self.include_fields.iter().any(|f| f == field))
+                && !self.exclude_fields.iter().any(|f| f == field))
+    }
+}
+
+/// Check a field name given to `--include-fields`/`--exclude-fields`.
+pub fn parse_field_name(name: &str) -> Result<String, String> {
+    let fields = serde_json::to_value(MeterReading::default()).map_err(|e| e.to_string())?;
+    let name = name.trim();
+    if fields.get(name).is_none() {
+        return Err(format!("unknown field `{}`", name));
+    }
+    Ok(name.to_string())
+}
+
+/// Serialize a reading payload, keeping the fields selected by `format` and
+/// renaming the keys to its case. serde's rename_all is compile-time, so the
+/// keys are rewritten on the JSON value.
+pub fn reading_json(reading: &MeterReading, format: &PayloadFormat) -> Result<serde_json::Value> {
+    let value = serde_json::to_value(Payload::new(reading))
+        .context("Failed to serialize reading to JSON")?;
+    let value = match value {
+        serde_json::Value::Object(fields) => {
+            let mut kept = serde_json::Map::new();
+            let mut units = serde_json::Map::new();
+            for (key, value) in fields.into_iter().filter(|(key, _)| format.keeps(key)) {
+                let name = format.case.apply(&key);
+                if let Some(unit) = field_unit(&key).filter(|_| format.units) {
+                    units.insert(name.clone(), unit.into());
+                }
+                kept.insert(name, value);
+            }
+            if format.units {
+                kept.insert("units".to_string(), units.into());
+            }
+            serde_json::Value::Object(kept)
+        }
+        value => value,
+    };
+    Ok(if format.flat {
+        let mut flat = serde_json::Map::new();
+        flatten_into(&mut flat, None, value);
+        serde_json::Value::Object(flat)
+    } else {
+        value
+    })
+}
+
+/// Insert the scalars of `value` into `flat`, keyed by their path below
+/// `prefix` with the object keys and array indices joined by dots.
+fn flatten_into(
+    flat: &mut serde_json::Map<String, serde_json::Value>,
+    prefix: Option<&str>,
+    value: serde_json::Value,
+) {
+    let key = |name: &str| match prefix {
+        Some(prefix) => format!("{}.{}", prefix, name),
+        None => name.to_string(),
+    };
+    match value {
+        serde_json::Value::Object(fields) => {
+            for (name, value) in fields {
+                flatten_into(flat, Some(&key(&name)), value);
+            }
+        }
+        serde_json::Value::Array(items) => {
+            for (index, value) in items.into_iter().enumerate() {
+                flatten_into(flat, Some(&key(&index.to_string())), value);
+            }
+        }
+        scalar => {
+            flat.insert(prefix.unwrap_or_default().to_string(), scalar);
+        }
+    }
+}
+
+/// The state payloads of `readings`. Given `previous`, the last full payload
+/// of each meter, every payload is reduced to what changed since the one
+/// before it and `previous` is moved on past them.
+fn state_values(
+    readings: &[MeterReading],
+    format: &PayloadFormat,
+    mut previous: Option<&mut HashMap<String, serde_json::Value>>,
+) -> Result<Vec<serde_json::Value>> {
+    readings
+        .iter()
+        .map(|reading| {
+            let value = reading_json(reading, format)?;
+            let Some(previous) = previous.as_deref_mut() else {
+                return Ok(value);
+            };
+            let delta = delta_json(previous.get(&reading.device_id), &value, format);
+            previous.insert(reading.device_id.clone(), value);
+            Ok(delta)
+        })
+        .collect()
+}
+
+/// The numeric fields of `current` that differ from `previous`, plus the
+/// fields that identify a payload; all of them when there is nothing to
+/// compare to.
+fn delta_json(
+    previous: Option<&serde_json::Value>,
+    current: &serde_json::Value,
+    format: &PayloadFormat,
+) -> serde_json::Value {
+    let (Some(serde_json::Value::Object(previous)), serde_json::Value::Object(current)) =
+        (previous, current)
+    else {
+        return current.clone();
+    };
+    let kept: Vec<_> = ALWAYS_KEPT
+        .iter()
+        .map(|key| format.case.apply(key))
+        .collect();
+    serde_json::Value::Object(
+        current
+            .iter()
+            .filter(|&(key, value)| {
+                kept.contains(key) || (value.is_number() && previous.get(key) != Some(value))
+            })
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect(),
+    )
+}
+
+/// Encode a payload for the state topic.
+fn encode(value: &serde_json::Value, format: &PayloadFormat) -> Result<Vec<u8>> {
+    match format.encoding {
+        Encoding::Json => Ok(value.to_string().into_bytes()),
+        Encoding::Msgpack => {
+            rmp_serde::to_vec_named(value).context("Failed to serialize reading to MessagePack")
+        }
+    }
+}
+
+/// Every set field of a reading as a (key, plain string value) pair, for
+/// publishing to per-field topics. Nulls and empty strings are skipped.
+fn field_values(reading: &MeterReading, format: &PayloadFormat) -> Result<Vec<(String, String)>> {
+    let fields = serde_json::to_value(reading).context("Failed to serialize reading")?;
+    Ok(fields
+        .as_object()
+        .into_iter()
+        .flatten()
+        .filter(|(key, _)| format.keeps(key))
+        .filter_map(|(key, value)| {
+            let value = match value {
+                serde_json::Value::String(s) if s.is_empty() => return None,
+                serde_json::Value::String(s) => s.clone(),
+                serde_json::Value::Number(n) => n.to_string(),
+                _ => return None,
+            };
+            Some((format.case.apply(key), value))
+        })
+        .collect())
+}
+
+/// Static facts about this instance for the retained `<topic>/info`
+/// message, to tell from the broker which version and setup is running.
+fn info_json(config: &Config) -> serde_json::Value {
+    serde_json::json!({
+        "device_id": config.device_id,
+        "version": env!("CARGO_PKG_VERSION"),
+        "ports": config.port,
+    })
+}
 
-    let mut opts = MqttOptions::new(&config.mqtt_client_id, &config.mqtt_host, config.mqtt_port);
-    opts.set_keep_alive(Duration::from_secs(60));
+/// The device info with the serial ports the meters were found on and the
+/// baud rate each is read at, once known.
+fn info_with_ports(info: &serde_json::Value, serial_ports: &BTreeMap<String, u32>) -> String {
+    let mut info = info.clone();
+    info["serial_ports"] = serial_ports
+        .iter()
+        .map(|(port, baud_rate)| serde_json::json!({ "port": port, "baud_rate": baud_rate }))
+        .collect();
+    info.to_string()
+}
 
-    let (client, mut connection) = Client::new(opts, 10);
+/// Placeholders `--mqtt-topic` may contain.
+const TOPIC_PLACEHOLDERS: &[&str] = &["device_id", "client_id", "field"];
+
+/// Check a `--mqtt-topic` for placeholders other than `TOPIC_PLACEHOLDERS`,
+/// so a typo fails at startup instead of being published literally.
+pub fn parse_topic_template(template: &str) -> Result<String, String> {
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        let close = rest[open..]
+            .find('}')
+            .ok_or_else(|| format!("unclosed `{{` in topic `{}`", template))?;
+        let name = &rest[open + 1..open + close];
+        if !TOPIC_PLACEHOLDERS.contains(&name) {
+            return Err(format!(
+                "unknown placeholder `{{{}}}`, expected one of {{{}}}",
+                name,
+                TOPIC_PLACEHOLDERS.join("}, {")
+            ));
+        }
+        rest = &rest[open + close + 1..];
+    }
+    Ok(template.to_string())
+}
+
+/// The template state and field topics are rendered from: the configured
+/// topic, with a device ID level added when reading several meters and a
+/// field level for `--split-topics`, unless it places those itself.
+fn topic_template(config: &Config) -> String {
+    let mut template = config.mqtt_topic.clone();
+    if config.port.len() > 1 && !template.contains("{device_id}") {
+        match template.find("/{field}") {
+            Some(at) => template.insert_str(at, "/{device_id}"),
+            None => template.push_str("/{device_id}"),
+        }
+    }
+    if !template.contains("{field}") {
+        template.push_str("/{field}");
+    }
+    template
+}
 
-    client
-        .publish(
+/// Fill in a topic template. Without a field (the JSON state topic) the
+/// `{field}` level is left out.
+fn render_topic(template: &str, device_id: &str, client_id: &str, field: Option<&str>) -> String {
+    let topic = template
+        .replace("{device_id}", device_id)
+        .replace("{client_id}", client_id);
+    match field {
+        Some(field) => topic.replace("{field}", field),
+        None => topic.replace("/{field}", "").replace("{field}", ""),
+    }
+}
+
+/// Map a numeric QoS level from the command line to the rumqttc enum.
+fn qos_from_level(level: u8) -> QoS {
+    match level {
+        0 => QoS::AtMostOnce,
+        1 => QoS::AtLeastOnce,
+        _ => QoS::ExactlyOnce,
+    }
+}
+
+impl MqttPublisher {
+    /// Create a client per broker. The actual connections are established
+    /// on the first publish.
+    pub fn new(config: &Config) -> Self {
+        if config.mqtt_username.is_none() && config.mqtt_password.is_some() {
+            warn!("MQTT password set without a username, connecting anonymously");
+        }
+
+        // Tasmota-style availability: the broker publishes Offline if we vanish,
+        // Online is published after every (re)connect
+        let topic = render_topic(
             &config.mqtt_topic,
-            QoS::AtMostOnce,
-            false,
-            payload.as_bytes(),
+            &config.device_id,
+            &config.mqtt_client_id,
+            None,
+        );
+        let lwt_topic = config
+            .mqtt_lwt_topic
+            .clone()
+            .unwrap_or_else(|| format!("{}/LWT", topic));
+        let qos = qos_from_level(config.mqtt_qos);
+
+        let brokers = config
+            .mqtt_brokers()
+            .into_iter()
+            .map(|(host, port)| {
+                let mut opts = MqttOptions::new(&config.mqtt_client_id, &host, port);
+                opts.set_keep_alive(Duration::from_secs(config.mqtt_keepalive_secs));
+                if let Some(username) = &config.mqtt_username {
+                    opts.set_credentials(username, config.mqtt_password.as_deref().unwrap_or(""));
+                }
+                opts.set_last_will(LastWill::new(&lwt_topic, "Offline", qos, true));
+
+                let (client, connection) = Client::new(opts, 10);
+                Broker {
+                    client,
+                    connection: Mutex::new(connection),
+                    host,
+                    port,
+                }
+            })
+            .collect();
+
+        Self {
+            brokers,
+            topic,
+            topic_template: topic_template(config),
+            client_id: config.mqtt_client_id.clone(),
+            format: config.payload_format(),
+            split_topics: config.split_topics,
+            lwt_topic,
+            info: info_json(config),
+            serial_ports: Mutex::new(BTreeMap::new()),
+            discovery: config.homeassistant_discovery,
+            discovered: Mutex::new(HashSet::new()),
+            delta_only: config.delta_only,
+            published: Mutex::new(HashMap::new()),
+            qos,
+            retain: config.mqtt_retain,
+            publish_timeout: Duration::from_secs(config.mqtt_publish_timeout_secs),
+        }
+    }
+
+    /// Run `publish` for every broker. A failing broker doesn't stop the
+    /// others; an error is only returned if all of them failed.
+    fn each_broker(&self, publish: impl Fn(&Broker) -> Result<()>) -> Result<()> {
+        let mut failed = 0;
+        let mut last_error = None;
+        for broker in &self.brokers {
+            if let Err(e) = publish(broker) {
+                if self.brokers.len() > 1 {
+                    error!(
+                        "Failed to publish to {}:{}: {:#}",
+                        broker.host, broker.port, e
+                    );
+                }
+                failed += 1;
+                last_error = Some(e);
+            }
+        }
+        match last_error {
+            Some(e) if failed == self.brokers.len() => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    /// The topic the JSON readings from `device_id` are published to.
+    fn state_topic(&self, device_id: &str) -> String {
+        render_topic(&self.topic_template, device_id, &self.client_id, None)
+    }
+
+    /// The topic a single field of `device_id` is published to with
+    /// `--split-topics`.
+    fn field_topic(&self, device_id: &str, field: &str) -> String {
+        render_topic(
+            &self.topic_template,
+            device_id,
+            &self.client_id,
+            Some(field),
         )
-        .context("Failed to queue MQTT publish")?;
+    }
+
+    fn info_payload(&self) -> String {
+        let serial_ports = self
+            .serial_ports
+            .lock()
+            .expect("serial ports lock poisoned");
+        info_with_ports(&self.info, &serial_ports)
+    }
+
+    /// Record the serial port a meter is read from for the device info,
+    /// replacing `previous` when probing found the meter elsewhere. Known
+    /// before the first publish connects, later changes are republished.
+    pub fn report_serial_port(&self, previous: Option<&(String, u32)>, current: &(String, u32)) {
+        {
+            let mut serial_ports = self
+                .serial_ports
+                .lock()
+                .expect("serial ports lock poisoned");
+            if let Some((path, _)) = previous {
+                serial_ports.remove(path);
+            }
+            serial_ports.insert(current.0.clone(), current.1);
+        }
+        if previous.is_some() {
+            let topic = format!("{}/info", self.topic);
+            let payload = self.info_payload();
+            if let Err(e) =
+                self.each_broker(|broker| self.send(broker, &topic, true, payload.as_bytes()))
+            {
+                warn!("Failed to publish device info: {:#}", e);
+            }
+        }
+    }
+
+    /// Publish a heartbeat to `<topic>/status`.
+    pub fn publish_status(&self, status: &serde_json::Value) -> Result<()> {
+        let topic = format!("{}/status", self.topic);
+        let payload = status.to_string();
+        self.each_broker(|broker| self.send(broker, &topic, false, payload.as_bytes()))
+    }
+
+    /// Publish reader metrics to `<topic>/metrics`.
+    pub fn publish_metrics(&self, metrics: &serde_json::Value) -> Result<()> {
+        let topic = format!("{}/metrics", self.topic);
+        let payload = metrics.to_string();
+        self.each_broker(|broker| self.send(broker, &topic, false, payload.as_bytes()))
+    }
+
+    /// Publish retained Home Assistant discovery configs for every numeric
+    /// reading field, pointing at the JSON state topic.
+    pub fn publish_discovery(&self, device_id: &str) -> Result<()> {
+        let fields =
+            serde_json::to_value(MeterReading::default()).context("Failed to serialize fields")?;
+        let node_id = discovery_node_id(device_id);
+        let state_topic = self.state_topic(device_id);
+
+        let configs: Vec<_> = fields
+            .as_object()
+            .into_iter()
+            .flat_map(|o| o.keys())
+            .filter(|field| self.format.keeps(field))
+            .filter_map(|field| {
+                let config = discovery_config(
+                    device_id,
+                    field,
+                    &self.format.case.apply(field),
+                    &state_topic,
+                    &self.lwt_topic,
+                )?;
+                let topic = format!("homeassistant/sensor/{}/{}/config", node_id, field);
+                Some((topic, config.to_string()))
+            })
+            .collect();
+        self.each_broker(|broker| {
+            for (topic, config) in &configs {
+                self.send(broker, topic, true, config.as_bytes())?;
+            }
+            Ok(())
+        })?;
 
-    // rumqttc requires driving the event loop to actually send the packet
-    for event in connection.iter() {
-        match event {
-            Ok(rumqttc::Event::Outgoing(rumqttc::Outgoing::Publish(_))) => {
-                info!(
-                    "Published to {} on {}:{}",
-                    config.mqtt_topic, config.mqtt_host, config.mqtt_port
-                );
-                break;
+        info!("Published Home Assistant discovery for {}", device_id);
+        Ok(())
+    }
+
+    /// Queue a message and drive the event loop until it is sent. For QoS 0
+    /// this returns once the packet is written; for QoS 1/2 it waits for the
+    /// PubAck/PubComp. Gives up after `--mqtt-publish-timeout-secs`, so a
+    /// broker that accepts the connection but stalls can't freeze the reader.
+    fn send(&self, broker: &Broker, topic: &str, retain: bool, payload: &[u8]) -> Result<()> {
+        let Broker {
+            client, host, port, ..
+        } = broker;
+
+        // Locked before queueing so concurrent senders (reader threads, the
+        // heartbeat) can't mistake each other's packets for their own
+        let mut connection = broker
+            .connection
+            .lock()
+            .expect("MQTT connection lock poisoned");
+
+        // try_publish so a backlog during a broker outage can't block the reader
+        client
+            .try_publish(topic, self.qos, retain, payload)
+            .context("Failed to queue MQTT publish")?;
+
+        // rumqttc requires driving the event loop to actually send the packet
+        let mut sent_pkid = None;
+        // Publishes still to be written, including an Online after connecting
+        let mut pending = 1;
+        let deadline = Instant::now() + self.publish_timeout;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let event = match connection.recv_timeout(remaining) {
+                Ok(event) => event,
+                Err(RecvTimeoutError::Timeout) => {
+                    return Err(anyhow::anyhow!(
+                        "Timed out after {}s publishing to {} on {}:{}",
+                        self.publish_timeout.as_secs(),
+                        topic,
+                        host,
+                        port
+                    ));
+                }
+                Err(RecvTimeoutError::Disconnected) => break,
+            };
+            match event {
+                Ok(Event::Incoming(Incoming::ConnAck(_))) => {
+                    info!("Connected to MQTT broker {}:{}", host, port);
+                    match client.try_publish(&self.lwt_topic, self.qos, true, "Online") {
+                        Ok(()) => pending += 1,
+                        Err(e) => warn!("Failed to queue Online to {}: {}", self.lwt_topic, e),
+                    }
+                    let info_topic = format!("{}/info", self.topic);
+                    let info = self.info_payload();
+                    match client.try_publish(&info_topic, self.qos, true, info) {
+                        Ok(()) => pending += 1,
+                        Err(e) => warn!("Failed to queue device info to {}: {}", info_topic, e),
+                    }
+                }
+                Ok(Event::Outgoing(Outgoing::Publish(pkid))) => {
+                    pending -= 1;
+                    if self.qos == QoS::AtMostOnce {
+                        if pending == 0 {
+                            info!("Published to {} on {}:{}", topic, host, port);
+                            break;
+                        }
+                        continue;
+                    }
+                    // The message itself was queued before the Online
+                    sent_pkid.get_or_insert(pkid);
+                }
+                Ok(Event::Incoming(Incoming::PubAck(ack)))
+                    if self.qos == QoS::AtLeastOnce && sent_pkid == Some(ack.pkid) =>
+                {
+                    info!("Published to {} on {}:{} (acknowledged)", topic, host, port);
+                    break;
+                }
+                Ok(Event::Incoming(Incoming::PubComp(comp)))
+                    if self.qos == QoS::ExactlyOnce && sent_pkid == Some(comp.pkid) =>
+                {
+                    info!("Published to {} on {}:{} (completed)", topic, host, port);
+                    break;
+                }
+                Err(ConnectionError::ConnectionRefused(
+                    code @ (ConnectReturnCode::BadUserNamePassword
+                    | ConnectReturnCode::NotAuthorized),
+                )) => {
+                    return Err(anyhow::anyhow!(
+                        "MQTT broker {}:{} rejected credentials ({:?}), check --mqtt-username and --mqtt-password",
+                        host,
+                        port,
+                        code
+                    ));
+                }
+                Err(e) => {
+                    warn!(
+                        "MQTT connection to {}:{} lost, reconnecting on next publish",
+                        host, port
+                    );
+                    return Err(anyhow::anyhow!("MQTT connection error: {}", e));
+                }
+                _ => continue,
             }
-            Ok(rumqttc::Event::Outgoing(rumqttc::Outgoing::Disconnect)) => break,
-            Err(e) => return Err(anyhow::anyhow!("MQTT connection error: {}", e)),
-            _ => continue,
         }
+
+        Ok(())
+    }
+
+    /// Publish `payload` to the state topic of `reading`'s meter, along with
+    /// discovery and the per-field topics of `reading`.
+    fn publish_state(&self, reading: &MeterReading, payload: &[u8]) -> Result<()> {
+        // Not locked while publishing; two readers of one meter at worst
+        // both publish the same retained configs
+        let discovered = || self.discovered.lock().expect("discovery lock poisoned");
+        if self.discovery && !discovered().contains(&reading.device_id) {
+            match self.publish_discovery(&reading.device_id) {
+                Ok(()) => {
+                    discovered().insert(reading.device_id.clone());
+                }
+                Err(e) => error!("Failed to publish discovery: {}", e),
+            }
+        }
+
+        let topic = self.state_topic(&reading.device_id);
+        let fields = if self.split_topics {
+            field_values(reading, &self.format)?
+        } else {
+            Vec::new()
+        };
+
+        self.each_broker(|broker| {
+            self.send(broker, &topic, self.retain, payload)?;
+            for (field, value) in &fields {
+                self.send(
+                    broker,
+                    &self.field_topic(&reading.device_id, field),
+                    self.retain,
+                    value.as_bytes(),
+                )?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Publish `readings` to the state topic of the last one, as an array
+    /// if `batch`. With `delta_only` the payloads are diffed against the
+    /// last published ones, which only move on once the publish succeeded
+    /// so a failed one doesn't lose changes.
+    fn publish_readings(&self, readings: &[MeterReading], batch: bool) -> Result<()> {
+        let Some(last) = readings.last() else {
+            return Ok(());
+        };
+        // Only this burst's meters, and not locked while publishing
+        let mut previous = self.delta_only.then(|| {
+            let published = self.published.lock().expect("published lock poisoned");
+            readings
+                .iter()
+                .filter_map(|reading| published.get_key_value(&reading.device_id))
+                .map(|(device_id, value)| (device_id.clone(), value.clone()))
+                .collect::<HashMap<_, _>>()
+        });
+        let mut values = state_values(readings, &self.format, previous.as_mut())?;
+        let value = if batch {
+            serde_json::Value::Array(values)
+        } else {
+            values.swap_remove(0)
+        };
+        self.publish_state(last, &encode(&value, &self.format)?)?;
+        if let Some(previous) = previous {
+            self.published
+                .lock()
+                .expect("published lock poisoned")
+                .extend(previous);
+        }
+        Ok(())
+    }
+}
+
+impl ReadingSink for MqttPublisher {
+    fn name(&self) -> &str {
+        "mqtt"
+    }
+
+    /// Publish a meter reading as JSON to the meter's state topic, preceded
+    /// by its Home Assistant discovery configs the first time the meter is
+    /// seen (the full device ID is only known once a telegram was read).
+    fn publish(&self, reading: &MeterReading) -> Result<()> {
+        self.publish_readings(std::slice::from_ref(reading), false)
+    }
+
+    /// Publish a burst as one JSON array to the state topic. Per-field
+    /// topics only get the last reading.
+    fn publish_batch(&self, readings: &[MeterReading]) -> Result<()> {
+        self.publish_readings(readings, true)
+    }
+}
+
+/// Publish a heartbeat every `interval` from a background thread, so a
+/// silent meter can be told apart from a dead process. Runs independently
+/// of the reader, even before the meter has ever answered.
+pub fn spawn_heartbeat(
+    publisher: Arc<MqttPublisher>,
+    interval: Duration,
+    metrics: Arc<Mutex<MetricsState>>,
+) {
+    let started = Instant::now();
+    std::thread::spawn(move || loop {
+        let last_read_ok = metrics.lock().expect("metrics lock poisoned").last_read_ok;
+        let status = heartbeat_json(last_read_ok, started.elapsed());
+        if let Err(e) = publisher.publish_status(&status) {
+            warn!("Failed to publish heartbeat: {:#}", e);
+        }
+        std::thread::sleep(interval);
+    });
+}
+
+fn heartbeat_json(last_read_ok: bool, uptime: Duration) -> serde_json::Value {
+    serde_json::json!({
+        "alive": true,
+        "last_read_ok": last_read_ok,
+        "uptime_secs": uptime.as_secs(),
+    })
+}
+
+/// Counters on the health of the reader itself, for `--metrics-every`.
+/// `baud_rate` is the serial port's current rate, None for other sources.
+pub fn metrics_json(metrics: &MetricsState, baud_rate: Option<u32>) -> serde_json::Value {
+    serde_json::json!({
+        "total_reads": metrics.reads_total,
+        "total_errors": metrics.read_errors_total,
+        "total_reconnects": metrics.reconnects_total,
+        "last_read_duration_ms": metrics.last_read_time.as_millis(),
+        "baud_rate": baud_rate,
+    })
+}
+
+/// Home Assistant only accepts `[a-zA-Z0-9_-]` in discovery node IDs.
+fn discovery_node_id(device_id: &str) -> String {
+    device_id
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Unit, device class and state class for a reading field, derived from its
+/// name suffix. Fields without a sensor mapping (e.g. `timestamp`) return None.
+fn sensor_class(field: &str) -> Option<(&'static str, Option<&'static str>, &'static str)> {
+    if field.ends_with("_kwh") {
+        Some(("kWh", Some("energy"), "total_increasing"))
+    } else if field.ends_with("_m3") {
+        Some(("m³", Some("gas"), "total_increasing"))
+    } else if field.ends_with("_kvarh") {
+        Some(("kvarh", None, "total_increasing"))
+    } else if field.ends_with("_voltage") {
+        Some(("V", Some("voltage"), "measurement"))
+    } else if field.ends_with("_current") {
+        Some(("A", Some("current"), "measurement"))
+    } else if field.ends_with("_power_kw") {
+        Some(("kW", Some("power"), "measurement"))
+    } else if field.ends_with("_power") {
+        Some(("W", Some("power"), "measurement"))
+    } else if field.ends_with("_apparent_va") {
+        Some(("VA", Some("apparent_power"), "measurement"))
+    } else if field.ends_with("_reactive_var") {
+        Some(("var", Some("reactive_power"), "measurement"))
+    } else if field.ends_with("_pf") {
+        Some(("", Some("power_factor"), "measurement"))
+    } else if field.ends_with("_kva") {
+        Some(("kVA", Some("apparent_power"), "measurement"))
+    } else if field == "frequency" {
+        Some(("Hz", Some("frequency"), "measurement"))
+    } else {
+        None
+    }
+}
+
+/// Unit of a reading field for `--with-units`, None for unitless fields.
+fn field_unit(field: &str) -> Option<&'static str> {
+    match field {
+        "total_power_avg" | "avg_power_w" => Some("W"),
+        _ => sensor_class(field)
+            .map(|(unit, _, _)| unit)
+            .filter(|unit| !unit.is_empty()),
+    }
+}
+
+/// Build the discovery config payload for a single reading field, whose key
+/// in the JSON state payload is `json_key`.
+fn discovery_config(
+    device_id: &str,
+    field: &str,
+    json_key: &str,
+    state_topic: &str,
+    availability_topic: &str,
+) -> Option<serde_json::Value> {
+    let (unit, device_class, state_class) = sensor_class(field)?;
+    let node_id = discovery_node_id(device_id);
+
+    let mut config = serde_json::json!({
+        "name": field.replace('_', " "),
+        "unique_id": format!("{}_{}", node_id, field),
+        "state_topic": state_topic,
+        "value_template": format!("{{{{ value_json.{} }}}}", json_key),
+        "state_class": state_class,
+        "availability_topic": availability_topic,
+        "payload_available": "Online",
+        "payload_not_available": "Offline",
+        "device": {
+            "identifiers": [node_id],
+            "name": device_id,
+        },
+    });
+    if !unit.is_empty() {
+        config["unit_of_measurement"] = unit.into();
+    }
+    if let Some(class) = device_class {
+        config["device_class"] = class.into();
+    }
+    Some(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[test]
+    fn qos_levels() {
+        assert_eq!(qos_from_level(0), QoS::AtMostOnce);
+        assert_eq!(qos_from_level(1), QoS::AtLeastOnce);
+        assert_eq!(qos_from_level(2), QoS::ExactlyOnce);
+    }
+
+    #[test]
+    fn discovery_config_energy() {
+        let config = discovery_config(
+            "ISk5MT174-0001",
+            "consumption_total_kwh",
+            "consumption_total_kwh",
+            "tele/ISK5MT174",
+            "tele/ISK5MT174/LWT",
+        )
+        .unwrap();
+        assert_eq!(config["unit_of_measurement"], "kWh");
+        assert_eq!(config["device_class"], "energy");
+        assert_eq!(config["state_class"], "total_increasing");
+        assert_eq!(config["state_topic"], "tele/ISK5MT174");
+        assert_eq!(config["availability_topic"], "tele/ISK5MT174/LWT");
+        assert_eq!(
+            config["value_template"],
+            "{{ value_json.consumption_total_kwh }}"
+        );
+        assert_eq!(config["unique_id"], "ISk5MT174-0001_consumption_total_kwh");
+    }
+
+    #[test]
+    fn discovery_config_power() {
+        let config = discovery_config(
+            "ISk5MT174-0001",
+            "total_power",
+            "totalPower",
+            "tele/ISK5MT174",
+            "tele/ISK5MT174/LWT",
+        )
+        .unwrap();
+        assert_eq!(config["unit_of_measurement"], "W");
+        assert_eq!(config["device_class"], "power");
+        assert_eq!(config["state_class"], "measurement");
+        assert_eq!(config["value_template"], "{{ value_json.totalPower }}");
+        assert_eq!(config["unique_id"], "ISk5MT174-0001_total_power");
+    }
+
+    #[test]
+    fn discovery_skips_non_numeric_fields() {
+        assert!(discovery_config(
+            "ISk5MT174-0001",
+            "timestamp",
+            "timestamp",
+            "tele/ISK5MT174",
+            "tele/ISK5MT174/LWT"
+        )
+        .is_none());
+        assert!(discovery_config(
+            "ISk5MT174-0001",
+            "device_id",
+            "device_id",
+            "tele/ISK5MT174",
+            "tele/ISK5MT174/LWT"
+        )
+        .is_none());
+    }
+
+    #[test]
+    fn discovery_node_id_sanitized() {
+        assert_eq!(discovery_node_id("ISk5 MT174/1"), "ISk5_MT174_1");
+    }
+
+    #[test]
+    fn state_topic_per_device() {
+        let config = Config::parse_from(["energymon", "--port", "/dev/ttyUSB0"]);
+        let publisher = MqttPublisher::new(&config);
+        assert_eq!(publisher.state_topic("ISk5MT174-0001"), "tele/ISK5MT174");
+        assert_eq!(publisher.lwt_topic, "tele/ISK5MT174/LWT");
+
+        let config = Config::parse_from(["energymon", "--port", "/dev/ttyUSB0,/dev/ttyUSB1"]);
+        let publisher = MqttPublisher::new(&config);
+        assert_eq!(
+            publisher.state_topic("ISk5MT174-0001"),
+            "tele/ISK5MT174/ISk5MT174-0001"
+        );
+    }
+
+    #[test]
+    fn metrics_payload() {
+        let metrics = MetricsState {
+            reads_total: 120,
+            read_errors_total: 3,
+            reconnects_total: 1,
+            last_read_time: Duration::from_millis(2450),
+            ..Default::default()
+        };
+        assert_eq!(
+            metrics_json(&metrics, Some(115200)),
+            serde_json::json!({
+                "total_reads": 120,
+                "total_errors": 3,
+                "total_reconnects": 1,
+                "last_read_duration_ms": 2450,
+                "baud_rate": 115200,
+            })
+        );
+        assert_eq!(
+            metrics_json(&metrics, None)["baud_rate"],
+            serde_json::Value::Null
+        );
+    }
+
+    #[test]
+    fn heartbeat_payload() {
+        let status = heartbeat_json(false, Duration::from_secs(90));
+        assert_eq!(
+            status.to_string(),
+            r#"{"alive":true,"last_read_ok":false,"uptime_secs":90}"#
+        );
+    }
+
+    #[test]
+    fn payload_is_tagged() {
+        let reading = MeterReading {
+            device_id: "ISk5MT174-0001".to_string(),
+            ..Default::default()
+        };
+        let json = serde_json::to_value(Payload::new(&reading)).unwrap();
+        let keys: Vec<_> = json.as_object().unwrap().keys().take(3).collect();
+        assert_eq!(keys, ["type", "schema_version", "device_id"]);
+        assert_eq!(json["type"], "energymon.reading");
+        assert_eq!(json["schema_version"], SCHEMA_VERSION);
+        assert_eq!(json["device_id"], "ISk5MT174-0001");
+    }
+
+    #[test]
+    fn camel_case_keys() {
+        let reading = MeterReading {
+            consumption_t1_kwh: 1.5,
+            ..Default::default()
+        };
+        let camel = PayloadFormat {
+            case: JsonCase::Camel,
+            ..Default::default()
+        };
+        let json = reading_json(&reading, &camel).unwrap();
+        assert_eq!(json["schemaVersion"], SCHEMA_VERSION);
+        assert_eq!(json["consumptionT1Kwh"], 1.5);
+        assert!(json.get("phase1Voltage").is_some());
+        assert!(json.get("consumption_t1_kwh").is_none());
+
+        let json = reading_json(&reading, &PayloadFormat::default()).unwrap();
+        assert_eq!(json["consumption_t1_kwh"], 1.5);
+    }
+
+    #[test]
+    fn split_topic_values() {
+        let reading = MeterReading {
+            device_id: "ISk5MT174-0001".to_string(),
+            consumption_total_kwh: 2686.675,
+            ..Default::default()
+        };
+        let values = field_values(&reading, &PayloadFormat::default()).unwrap();
+        assert!(values.contains(&("consumption_total_kwh".to_string(), "2686.675".to_string())));
+        assert!(values.contains(&("device_id".to_string(), "ISk5MT174-0001".to_string())));
+        // Unset optional and empty fields are not published
+        assert!(values.iter().all(|(k, _)| k != "meter_timestamp"));
+        assert!(values.iter().all(|(k, _)| k != "import_power_kw"));
+    }
+
+    #[test]
+    fn field_filter() {
+        let reading = MeterReading {
+            device_id: "ISk5MT174-0001".to_string(),
+            total_power: 430.36,
+            ..Default::default()
+        };
+        let include = PayloadFormat {
+            include_fields: vec!["total_power".to_string(), "net_power".to_string()],
+            exclude_fields: vec!["net_power".to_string()],
+            ..Default::default()
+        };
+        let json = reading_json(&reading, &include).unwrap();
+        let keys: Vec<_> = json.as_object().unwrap().keys().collect();
+        assert_eq!(
+            keys,
+            [
+                "type",
+                "schema_version",
+                "device_id",
+                "total_power",
+                "timestamp"
+            ]
+        );
+
+        let exclude = PayloadFormat {
+            exclude_fields: vec!["total_power".to_string()],
+            ..Default::default()
+        };
+        let json = reading_json(&reading, &exclude).unwrap();
+        assert!(json.get("total_power").is_none());
+        assert!(json.get("phase1_voltage").is_some());
+
+        assert_eq!(
+            parse_field_name(" total_power"),
+            Ok("total_power".to_string())
+        );
+        assert!(parse_field_name("total_watts").is_err());
     }
 
-    client.disconnect().ok();
-    Ok(())
+    #[test]
+    fn msgpack_round_trip() {
+        let reading = MeterReading {
+            device_id: "ISk5MT174-0001".to_string(),
+            total_power: 430.36,
+            gas_total_m3: Some(1234.5),
+            ..Default::default()
+        };
+        let format = PayloadFormat {
+            encoding: Encoding::Msgpack,
+            ..Default::default()
+        };
+        let bytes = encode(&reading_json(&reading, &format).unwrap(), &format).unwrap();
+        let decoded: serde_json::Value = rmp_serde::from_slice(&bytes).unwrap();
+        assert_eq!(
+            decoded,
+            reading_json(&reading, &PayloadFormat::default()).unwrap()
+        );
+        assert!(bytes.len() < encode(&decoded, &PayloadFormat::default()).unwrap().len());
+    }
+
+    #[test]
+    fn publish_times_out_on_stalled_broker() {
+        // Accepts the TCP connection but never answers the CONNECT
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port().to_string();
+        let stalled = std::thread::spawn(move || listener.accept());
+        let config = Config::parse_from([
+            "energymon",
+            "--mqtt-host",
+            "127.0.0.1",
+            "--mqtt-port",
+            &port,
+            "--mqtt-publish-timeout-secs",
+            "1",
+        ]);
+        let publisher = MqttPublisher::new(&config);
+
+        let started = Instant::now();
+        let err = ReadingSink::publish(&publisher, &MeterReading::default()).unwrap_err();
+        assert!(err.to_string().contains("Timed out"), "{}", err);
+        assert!(started.elapsed() < Duration::from_secs(3));
+        drop(stalled);
+    }
+
+    #[test]
+    fn topic_placeholders() {
+        assert!(parse_topic_template("tele/{device_id}/{field}").is_ok());
+        let err = parse_topic_template("tele/{location}").unwrap_err();
+        assert!(err.contains("{location}"), "{}", err);
+        assert!(parse_topic_template("tele/{device_id").is_err());
+        assert!(Config::try_parse_from(["energymon", "--mqtt-topic", "a/{foo}"]).is_err());
+
+        let config = Config::parse_from([
+            "energymon",
+            "--mqtt-topic",
+            "home/{client_id}/{device_id}/{field}",
+            "--mqtt-client-id",
+            "attic",
+        ]);
+        let publisher = MqttPublisher::new(&config);
+        assert_eq!(
+            publisher.state_topic("ISk5MT174-0001"),
+            "home/attic/ISk5MT174-0001"
+        );
+        assert_eq!(
+            publisher.field_topic("ISk5MT174-0001", "total_power"),
+            "home/attic/ISk5MT174-0001/total_power"
+        );
+        // Topics published before a reading use the configured device ID
+        assert_eq!(publisher.lwt_topic, "home/attic/ISk5MT174/LWT");
+
+        let config =
+            Config::parse_from(["energymon", "--mqtt-topic", "e/{field}/v", "--port", "a,b"]);
+        let publisher = MqttPublisher::new(&config);
+        assert_eq!(publisher.field_topic("m1", "frequency"), "e/m1/frequency/v");
+        assert_eq!(publisher.state_topic("m1"), "e/m1/v");
+    }
+
+    #[test]
+    fn device_info() {
+        let config = Config::parse_from(["energymon", "--port", "/dev/ttyUSB0"]);
+        let info = info_json(&config);
+        assert_eq!(info["device_id"], "ISk5MT174");
+        assert_eq!(info["version"], env!("CARGO_PKG_VERSION"));
+        assert_eq!(info["ports"], serde_json::json!(["/dev/ttyUSB0"]));
+
+        let serial_ports = BTreeMap::from([("/dev/ttyUSB0".to_string(), 9600)]);
+        let info: serde_json::Value =
+            serde_json::from_str(&info_with_ports(&info, &serial_ports)).unwrap();
+        assert_eq!(
+            info["serial_ports"],
+            serde_json::json!([{ "port": "/dev/ttyUSB0", "baud_rate": 9600 }])
+        );
+        assert_eq!(info["version"], env!("CARGO_PKG_VERSION"));
+    }
+
+    #[test]
+    fn flat_json_has_no_nesting() {
+        let mut reading = MeterReading {
+            total_power: 430.36,
+            ..Default::default()
+        };
+        reading.other_obis.insert(
+            "1-0:5.8.0".to_string(),
+            crate::meter::ObisValue {
+                value: 12.5,
+                unit: "kvarh".to_string(),
+            },
+        );
+        let format = PayloadFormat {
+            flat: true,
+            ..Default::default()
+        };
+        let json = reading_json(&reading, &format).unwrap();
+        let fields = json.as_object().unwrap();
+        assert!(fields.values().all(|v| !v.is_object() && !v.is_array()));
+        assert_eq!(fields["other_obis.1-0:5.8.0.value"], 12.5);
+        assert_eq!(fields["other_obis.1-0:5.8.0.unit"], "kvarh");
+        assert_eq!(fields["total_power"], 430.36);
+        assert!(!fields.contains_key("other_obis"));
+
+        let mut flat = serde_json::Map::new();
+        flatten_into(&mut flat, None, serde_json::json!({"a": [1, {"b": null}]}));
+        assert_eq!(
+            serde_json::Value::Object(flat),
+            serde_json::json!({"a.0": 1, "a.1.b": null})
+        );
+    }
+
+    #[test]
+    fn units_describe_fields() {
+        let reading = MeterReading {
+            consumption_total_kwh: 2686.675,
+            phase1_voltage: 231.3,
+            ..Default::default()
+        };
+        let json = reading_json(&reading, &PayloadFormat::default()).unwrap();
+        assert!(json.get("units").is_none());
+
+        let format = PayloadFormat {
+            units: true,
+            case: JsonCase::Camel,
+            exclude_fields: vec!["phase2_voltage".to_string()],
+            ..Default::default()
+        };
+        let json = reading_json(&reading, &format).unwrap();
+        // Values stay where they are
+        assert_eq!(json["consumptionTotalKwh"], 2686.675);
+        let units = json["units"].as_object().unwrap();
+        assert_eq!(units["consumptionTotalKwh"], "kWh");
+        assert_eq!(units["phase1Voltage"], "V");
+        assert_eq!(units["phase1Current"], "A");
+        assert_eq!(units["totalPower"], "W");
+        assert_eq!(units["apparentPowerKva"], "kVA");
+        assert_eq!(units["frequency"], "Hz");
+        assert_eq!(units["avgPowerW"], "W");
+        assert!(!units.contains_key("phase2Voltage"));
+        assert!(!units.contains_key("phase1Pf"));
+        assert!(!units.contains_key("deviceId"));
+    }
+
+    #[test]
+    fn batch_is_an_array() {
+        let readings: Vec<_> = [430.36, 512.0]
+            .into_iter()
+            .map(|total_power| MeterReading {
+                total_power,
+                ..Default::default()
+            })
+            .collect();
+        let items = state_values(&readings, &PayloadFormat::default(), None).unwrap();
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0]["total_power"], 430.36);
+        assert_eq!(items[1]["total_power"], 512.0);
+        assert_eq!(items[1]["type"], "energymon.reading");
+    }
+
+    #[test]
+    fn delta_only_omits_unchanged_fields() {
+        let first = MeterReading {
+            device_id: "ISk5MT174-0001".to_string(),
+            consumption_total_kwh: 2686.675,
+            total_power: 430.36,
+            timestamp: "2026-02-27 17:26:26".to_string(),
+            ..Default::default()
+        };
+        let second = MeterReading {
+            total_power: 512.0,
+            text_message: "Onderhoud 27/02".to_string(),
+            timestamp: "2026-02-27 17:26:36".to_string(),
+            ..first.clone()
+        };
+        let format = PayloadFormat {
+            case: JsonCase::Camel,
+            ..Default::default()
+        };
+        let mut previous = HashMap::new();
+        let values = state_values(&[first.clone(), second], &format, Some(&mut previous)).unwrap();
+
+        // The first reading has nothing to compare to and is sent whole
+        assert_eq!(values[0], reading_json(&first, &format).unwrap());
+        let keys: Vec<_> = values[1].as_object().unwrap().keys().collect();
+        assert_eq!(
+            keys,
+            [
+                "type",
+                "schemaVersion",
+                "deviceId",
+                "totalPower",
+                "timestamp"
+            ]
+        );
+        assert_eq!(values[1]["totalPower"], 512.0);
+        assert_eq!(previous["ISk5MT174-0001"]["totalPower"], 512.0);
+    }
 }
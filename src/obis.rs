@@ -0,0 +1,250 @@
+use std::collections::HashMap;
+
+use crate::meter::MeterReading;
+
+/// A numeric reading field an OBIS code can be mapped to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    ConsumptionTotal,
+    ConsumptionT1,
+    ConsumptionT2,
+    ProductionTotal,
+    ProductionT1,
+    ProductionT2,
+    ReactiveImportTotal,
+    ReactiveExportTotal,
+    ImportPower,
+    ExportPower,
+    CombinedPower,
+    NetPower,
+    ApparentPower,
+    Phase1ApparentPower,
+    Phase2ApparentPower,
+    Phase3ApparentPower,
+    Phase1ImportPower,
+    Phase2ImportPower,
+    Phase3ImportPower,
+    Phase1ExportPower,
+    Phase2ExportPower,
+    Phase3ExportPower,
+    TotalCurrent,
+    Phase1Voltage,
+    Phase2Voltage,
+    Phase3Voltage,
+    Phase1Current,
+    Phase2Current,
+    Phase3Current,
+    Frequency,
+    Phase1Pf,
+    Phase2Pf,
+    Phase3Pf,
+    TotalPf,
+}
+
+/// Every field with its `MeterReading` name and default OBIS code.
+const FIELDS: &[(Field, &str, &str)] = &[
+    (
+        Field::ConsumptionTotal,
+        "consumption_total_kwh",
+        "1-0:1.8.0",
+    ),
+    (Field::ConsumptionT1, "consumption_t1_kwh", "1-0:1.8.1"),
+    (Field::ConsumptionT2, "consumption_t2_kwh", "1-0:1.8.2"),
+    (Field::ProductionTotal, "production_total_kwh", "1-0:2.8.0"),
+    (Field::ProductionT1, "production_t1_kwh", "1-0:2.8.1"),
+    (Field::ProductionT2, "production_t2_kwh", "1-0:2.8.2"),
+    (
+        Field::ReactiveImportTotal,
+        "reactive_import_total_kvarh",
+        "1-0:3.8.0",
+    ),
+    (
+        Field::ReactiveExportTotal,
+        "reactive_export_total_kvarh",
+        "1-0:4.8.0",
+    ),
+    (Field::ImportPower, "import_power_kw", "1-0:1.7.0"),
+    (Field::ExportPower, "export_power_kw", "1-0:2.7.0"),
+    (Field::CombinedPower, "combined_power_kw", "1-0:15.7.0"),
+    (Field::NetPower, "net_power_kw", "1-0:16.7.0"),
+    (Field::ApparentPower, "apparent_power_kva", "1-0:9.7.0"),
+    (
+        Field::Phase1ApparentPower,
+        "phase1_apparent_power_kva",
+        "1-0:29.7.0",
+    ),
+    (
+        Field::Phase2ApparentPower,
+        "phase2_apparent_power_kva",
+        "1-0:49.7.0",
+    ),
+    (
+        Field::Phase3ApparentPower,
+        "phase3_apparent_power_kva",
+        "1-0:69.7.0",
+    ),
+    (
+        Field::Phase1ImportPower,
+        "phase1_import_power_kw",
+        "1-0:21.7.0",
+    ),
+    (
+        Field::Phase2ImportPower,
+        "phase2_import_power_kw",
+        "1-0:41.7.0",
+    ),
+    (
+        Field::Phase3ImportPower,
+        "phase3_import_power_kw",
+        "1-0:61.7.0",
+    ),
+    (
+        Field::Phase1ExportPower,
+        "phase1_export_power_kw",
+        "1-0:22.7.0",
+    ),
+    (
+        Field::Phase2ExportPower,
+        "phase2_export_power_kw",
+        "1-0:42.7.0",
+    ),
+    (
+        Field::Phase3ExportPower,
+        "phase3_export_power_kw",
+        "1-0:62.7.0",
+    ),
+    (Field::TotalCurrent, "total_current", "1-0:90.7.0"),
+    (Field::Phase1Voltage, "phase1_voltage", "1-0:32.7.0"),
+    (Field::Phase2Voltage, "phase2_voltage", "1-0:52.7.0"),
+    (Field::Phase3Voltage, "phase3_voltage", "1-0:72.7.0"),
+    (Field::Phase1Current, "phase1_current", "1-0:31.7.0"),
+    (Field::Phase2Current, "phase2_current", "1-0:51.7.0"),
+    (Field::Phase3Current, "phase3_current", "1-0:71.7.0"),
+    (Field::Frequency, "frequency", "1-0:14.7.0"),
+    (Field::Phase1Pf, "phase1_pf", "1-0:33.7.0"),
+    (Field::Phase2Pf, "phase2_pf", "1-0:53.7.0"),
+    (Field::Phase3Pf, "phase3_pf", "1-0:73.7.0"),
+    (Field::TotalPf, "total_pf", "1-0:13.7.0"),
+];
+
+impl Field {
+    /// Store a parsed value in the matching reading field.
+    pub fn set(self, reading: &mut MeterReading, value: f64) {
+        match self {
+            Field::ConsumptionTotal => reading.consumption_total_kwh = value,
+            Field::ConsumptionT1 => reading.consumption_t1_kwh = value,
+            Field::ConsumptionT2 => reading.consumption_t2_kwh = value,
+            Field::ProductionTotal => reading.production_total_kwh = value,
+            Field::ProductionT1 => reading.production_t1_kwh = value,
+            Field::ProductionT2 => reading.production_t2_kwh = value,
+            Field::ReactiveImportTotal => reading.reactive_import_total_kvarh = value,
+            Field::ReactiveExportTotal => reading.reactive_export_total_kvarh = value,
+            Field::ImportPower => reading.import_power_kw = Some(value),
+            Field::ExportPower => reading.export_power_kw = Some(value),
+            Field::CombinedPower => reading.combined_power_kw = Some(value),
+            Field::NetPower => reading.net_power_kw = Some(value),
+            Field::ApparentPower => reading.apparent_power_kva = Some(value),
+            Field::Phase1ApparentPower => reading.phase1_apparent_power_kva = Some(value),
+            Field::Phase2ApparentPower => reading.phase2_apparent_power_kva = Some(value),
+            Field::Phase3ApparentPower => reading.phase3_apparent_power_kva = Some(value),
+            Field::Phase1ImportPower => reading.phase1_import_power_kw = Some(value),
+            Field::Phase2ImportPower => reading.phase2_import_power_kw = Some(value),
+            Field::Phase3ImportPower => reading.phase3_import_power_kw = Some(value),
+            Field::Phase1ExportPower => reading.phase1_export_power_kw = Some(value),
+            Field::Phase2ExportPower => reading.phase2_export_power_kw = Some(value),
+            Field::Phase3ExportPower => reading.phase3_export_power_kw = Some(value),
+            Field::TotalCurrent => reading.total_current = Some(value),
+            Field::Phase1Voltage => reading.phase1_voltage = value,
+            Field::Phase2Voltage => reading.phase2_voltage = value,
+            Field::Phase3Voltage => reading.phase3_voltage = value,
+            Field::Phase1Current => reading.phase1_current = value,
+            Field::Phase2Current => reading.phase2_current = value,
+            Field::Phase3Current => reading.phase3_current = value,
+            Field::Frequency => reading.frequency = value,
+            Field::Phase1Pf => reading.phase1_pf = value,
+            Field::Phase2Pf => reading.phase2_pf = value,
+            Field::Phase3Pf => reading.phase3_pf = value,
+            Field::TotalPf => reading.total_pf = Some(value),
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        FIELDS
+            .iter()
+            .find(|(_, field_name, _)| *field_name == name)
+            .map(|(field, _, _)| *field)
+    }
+}
+
+/// Which OBIS code fills which reading field. Starts from the standard codes
+/// and can be overridden per meter model without recompiling.
+#[derive(Debug, Clone)]
+pub struct ObisMap(HashMap<String, Field>);
+
+impl Default for ObisMap {
+    fn default() -> Self {
+        Self(
+            FIELDS
+                .iter()
+                .map(|(field, _, code)| (code.to_string(), *field))
+                .collect(),
+        )
+    }
+}
+
+impl ObisMap {
+    /// The default map with overrides applied. An override replaces the
+    /// field's default code.
+    pub fn with_overrides(overrides: &[(Field, String)]) -> Self {
+        let mut map = Self::default();
+        for (field, code) in overrides {
+            map.0.retain(|_, mapped| mapped != field);
+            map.0.insert(code.clone(), *field);
+        }
+        map
+    }
+
+    /// The field an OBIS code (without `*255` suffix) maps to.
+    pub fn get(&self, code: &str) -> Option<Field> {
+        self.0.get(code).copied()
+    }
+}
+
+/// Parse a `field=code` mapping override from the command line.
+pub fn parse_override(s: &str) -> Result<(Field, String), String> {
+    let (name, code) = s
+        .split_once('=')
+        .ok_or_else(|| format!("expected FIELD=CODE, got `{}`", s))?;
+    let field = Field::from_name(name.trim())
+        .ok_or_else(|| format!("unknown or non-numeric field `{}`", name.trim()))?;
+    Ok((field, code.trim().to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_codes() {
+        let map = ObisMap::default();
+        assert_eq!(map.get("1-0:1.8.0"), Some(Field::ConsumptionTotal));
+        assert_eq!(map.get("1-0:73.7.0"), Some(Field::Phase3Pf));
+        assert_eq!(map.get("1-0:96.1.0"), None);
+    }
+
+    #[test]
+    fn override_replaces_default_code() {
+        let entry = parse_override("consumption_total_kwh = 1.8.0").unwrap();
+        assert_eq!(entry, (Field::ConsumptionTotal, "1.8.0".to_string()));
+        let map = ObisMap::with_overrides(&[entry]);
+        assert_eq!(map.get("1.8.0"), Some(Field::ConsumptionTotal));
+        assert_eq!(map.get("1-0:1.8.0"), None);
+        assert_eq!(map.get("1-0:1.8.1"), Some(Field::ConsumptionT1));
+    }
+
+    #[test]
+    fn invalid_overrides() {
+        assert!(parse_override("consumption_total_kwh").is_err());
+        assert!(parse_override("timestamp=0-0:1.0.0").is_err());
+    }
+}
@@ -0,0 +1,149 @@
+use anyhow::{bail, Context, Result};
+use log::{debug, info, warn};
+use std::ffi::CString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{FileTypeExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::meter::MeterReading;
+use crate::mqtt::{self, PayloadFormat};
+use crate::sink::ReadingSink;
+
+/// Writes readings as JSON lines to a named pipe for log-shipping agents.
+/// The pipe is opened non-blocking, so a missing or slow reader costs
+/// readings rather than stalling the read loop.
+pub struct PipeWriter {
+    path: PathBuf,
+    file: Mutex<Option<File>>,
+    format: PayloadFormat,
+}
+
+impl PipeWriter {
+    /// Create the FIFO if it doesn't exist yet. It is opened on the first
+    /// write that finds a reader.
+    pub fn create(path: &Path, format: PayloadFormat) -> Result<Self> {
+        match fs::metadata(path) {
+            Ok(meta) if meta.file_type().is_fifo() => {}
+            Ok(_) => bail!("{} exists and is not a named pipe", path.display()),
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                let c_path = CString::new(path.as_os_str().as_bytes())
+                    .context("Pipe path contains a NUL byte")?;
+                // SAFETY: c_path is a valid NUL-terminated string
+                if unsafe { libc::mkfifo(c_path.as_ptr(), 0o644) } != 0 {
+                    return Err(std::io::Error::last_os_error())
+                        .with_context(|| format!("Failed to create pipe {}", path.display()));
+                }
+                info!("Created named pipe {}", path.display());
+            }
+            Err(e) => {
+                return Err(e).with_context(|| format!("Failed to inspect {}", path.display()))
+            }
+        }
+        Ok(Self {
+            path: path.to_path_buf(),
+            file: Mutex::new(None),
+            format,
+        })
+    }
+
+    /// Write one JSON line. Lines are far below PIPE_BUF, so each write is
+    /// all or nothing; without a reader, or with a full pipe, the line is
+    /// dropped. A reader that went away is reopened on a later write.
+    pub fn send(&self, json: &serde_json::Value) {
+        let mut file = self.file.lock().expect("pipe lock poisoned");
+        if file.is_none() {
+            match OpenOptions::new()
+                .write(true)
+                .custom_flags(libc::O_NONBLOCK)
+                .open(&self.path)
+            {
+                Ok(opened) => {
+                    info!("Reader attached to {}", self.path.display());
+                    *file = Some(opened);
+                }
+                // No process has the pipe open for reading
+                Err(e) if e.raw_os_error() == Some(libc::ENXIO) => {
+                    debug!("No reader on {}, dropping reading", self.path.display());
+                    return;
+                }
+                Err(e) => {
+                    warn!("Failed to open {}: {}", self.path.display(), e);
+                    return;
+                }
+            }
+        }
+
+        let mut line = json.to_string();
+        line.push('\n');
+        let Some(pipe) = &mut *file else {
+            return;
+        };
+        match pipe.write_all(line.as_bytes()) {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                warn!(
+                    "Reader of {} is not keeping up, dropping reading",
+                    self.path.display()
+                );
+            }
+            Err(e) => {
+                // EPIPE once the reader closed its end
+                debug!("Reader of {} went away: {}", self.path.display(), e);
+                *file = None;
+            }
+        }
+    }
+}
+
+impl ReadingSink for PipeWriter {
+    fn name(&self) -> &str {
+        "pipe"
+    }
+
+    fn publish(&self, reading: &MeterReading) -> Result<()> {
+        self.send(&mqtt::reading_json(reading, &self.format)?);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+
+    #[test]
+    fn writes_lines_while_a_reader_is_attached() {
+        let path = std::env::temp_dir().join(format!("energymon-{}.fifo", std::process::id()));
+        let pipe = PipeWriter::create(&path, PayloadFormat::default()).unwrap();
+        assert!(fs::metadata(&path).unwrap().file_type().is_fifo());
+
+        // Nobody reading yet: dropped without blocking
+        pipe.send(&serde_json::json!({"total_power": 1}));
+        assert!(pipe.file.lock().unwrap().is_none());
+
+        let reader = OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(&path)
+            .unwrap();
+        pipe.send(&serde_json::json!({"total_power": 430.36}));
+        let mut line = String::new();
+        BufReader::new(&reader).read_line(&mut line).unwrap();
+        assert_eq!(line, "{\"total_power\":430.36}\n");
+
+        // The reader leaves: the next write fails and the pipe is reopened later
+        drop(reader);
+        pipe.send(&serde_json::json!({}));
+        assert!(pipe.file.lock().unwrap().is_none());
+
+        // An existing pipe is reused, anything else is refused
+        assert!(PipeWriter::create(&path, PayloadFormat::default()).is_ok());
+        fs::remove_file(&path).unwrap();
+        fs::write(&path, "").unwrap();
+        assert!(PipeWriter::create(&path, PayloadFormat::default()).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,8 +1,11 @@
-use anyhow::{bail, Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
 use log::{debug, info, warn};
-use std::io::{BufRead, BufReader};
-use std::time::Duration;
+use regex::Regex;
+use std::io::{self, BufReader, Read};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
 
+use crate::protocol::read_line;
 
 pub const IEC_INIT_SEQUENCE: &[u8] = b"\x2F\x3F\x21\x0D\x0A"; // /?!\r\n
 pub const BAUD_RATE: u32 = 300;
@@ -10,62 +13,306 @@ pub const DATA_BITS: serialport::DataBits = serialport::DataBits::Seven;
 pub const PARITY: serialport::Parity = serialport::Parity::Even;
 pub const STOP_BITS: serialport::StopBits = serialport::StopBits::One;
 
-const PROBE_TIMEOUT: Duration = Duration::from_secs(3);
+/// Character format of the serial line: data bits and parity.
+pub type DataFormat = (serialport::DataBits, serialport::Parity);
+
+/// Formats tried while probing, in order: 7E1 for IEC 62056-21 meters,
+/// 8N1 for DSMR P1 ports and some optical heads.
+pub const DATA_FORMATS: [DataFormat; 2] = [
+    (DATA_BITS, PARITY),
+    (serialport::DataBits::Eight, serialport::Parity::None),
+];
+
+/// Ports probed when no pattern is configured.
+pub const DEFAULT_PORT_PATTERN: &str = "ttyUSB";
+
+/// IEC 62056-21 baud rates tried by `--baud-scan` after 300 baud.
+const SCAN_BAUD_RATES: [u32; 6] = [600, 1200, 2400, 4800, 9600, 19200];
+
+/// Default time to let the meter wake up after the init sequence.
+pub const DEFAULT_INIT_WAKE: Duration = Duration::from_millis(500);
+
+/// Default time to wait for the identification line while probing. At 300
+/// baud a 7E1 character takes 33 ms, so a 30 character ident alone needs a
+/// second on top of the meter's response delay.
+pub const DEFAULT_PROBE_TIMEOUT: Duration = Duration::from_secs(6);
+
+/// How long DTR is held low by `--dtr-pulse` to reset the IR head.
+const DTR_PULSE: Duration = Duration::from_millis(100);
+
+/// Modem control lines driven after opening a port. Many IR heads (like the
+/// Weidmann head) are powered from DTR; self-powered ones may need RTS low.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineControl {
+    pub dtr: bool,
+    pub rts: bool,
+    /// Pulse DTR low before setting it, for heads that reset on DTR.
+    pub dtr_pulse: bool,
+}
+
+impl Default for LineControl {
+    /// DTR and RTS high, matching pyserial defaults.
+    fn default() -> Self {
+        Self {
+            dtr: true,
+            rts: true,
+            dtr_pulse: false,
+        }
+    }
+}
+
+impl LineControl {
+    /// Drive DTR and RTS on an open port.
+    fn apply(&self, port: &mut dyn serialport::SerialPort) -> serialport::Result<()> {
+        if self.dtr_pulse {
+            port.write_data_terminal_ready(false)?;
+            std::thread::sleep(DTR_PULSE);
+        }
+        port.write_data_terminal_ready(self.dtr)?;
+        port.write_request_to_send(self.rts)
+    }
+}
+
+/// Serial timing settings shared by probing and reading.
+#[derive(Debug, Clone)]
+pub struct SerialSettings {
+    /// How long to wait after sending the init sequence. Slow meters need
+    /// more time to wake up before they respond.
+    pub init_wake: Duration,
+    /// Read timeout while probing for the identification line, separate
+    /// from the timeout of steady-state reads.
+    pub probe_timeout: Duration,
+    /// Data bits and parity, 7E1 unless probing found another format.
+    pub data_format: DataFormat,
+    /// Port name patterns considered when probing, see `matches_pattern`.
+    pub port_patterns: Vec<String>,
+    /// DTR/RTS state set after opening a port.
+    pub lines: LineControl,
+    /// When no meter answers at 300 baud, retry at each of `SCAN_BAUD_RATES`
+    /// for meters that send at their native rate right away.
+    pub baud_scan: bool,
+    /// Keep reading telegrams a woken meter streams instead of sending the
+    /// init sequence before each one.
+    pub persistent_session: bool,
+    /// Check the identification line of a `--port` meter before reading.
+    pub verify_ident: bool,
+    /// Identification the meter must match instead of containing the
+    /// device ID, see `matches_device_id`.
+    pub device_pattern: Option<Regex>,
+    /// First character of the identification line, `/` in IEC 62056-21.
+    pub start: char,
+}
+
+impl Default for SerialSettings {
+    fn default() -> Self {
+        Self {
+            init_wake: DEFAULT_INIT_WAKE,
+            probe_timeout: DEFAULT_PROBE_TIMEOUT,
+            data_format: (DATA_BITS, PARITY),
+            port_patterns: vec![DEFAULT_PORT_PATTERN.to_string()],
+            lines: LineControl::default(),
+            baud_scan: false,
+            persistent_session: false,
+            verify_ident: true,
+            device_pattern: None,
+            start: '/',
+        }
+    }
+}
 
 /// Open a serial port with IEC 62056-21 settings.
-/// Sets DTR and RTS high to match pyserial defaults — the Weidmann IR head
-/// uses DTR to power its IR LED.
-pub fn open_port(path: &str, timeout: Duration) -> Result<Box<dyn serialport::SerialPort>> {
-    let mut port = serialport::new(path, BAUD_RATE)
-        .data_bits(DATA_BITS)
-        .parity(PARITY)
+/// Sets DTR and RTS as configured, high by default to match pyserial — the
+/// Weidmann IR head uses DTR to power its IR LED.
+pub fn open_port(
+    path: &str,
+    format: DataFormat,
+    lines: &LineControl,
+    timeout: Duration,
+) -> Result<Box<dyn serialport::SerialPort>> {
+    open_port_at(path, BAUD_RATE, format, lines, timeout)
+}
+
+/// Open a serial port with IEC 62056-21 settings at a fixed baud rate, as
+/// used by Mode D meters that push telegrams without being asked.
+pub fn open_port_at(
+    path: &str,
+    baud_rate: u32,
+    (data_bits, parity): DataFormat,
+    lines: &LineControl,
+    timeout: Duration,
+) -> Result<Box<dyn serialport::SerialPort>> {
+    let mut port = serialport::new(path, baud_rate)
+        .data_bits(data_bits)
+        .parity(parity)
         .stop_bits(STOP_BITS)
         .timeout(timeout)
         .open()
         .with_context(|| format!("Failed to open serial port {}", path))?;
 
-    port.write_data_terminal_ready(true)
-        .context("Failed to set DTR")?;
-    port.write_request_to_send(true)
-        .context("Failed to set RTS")?;
+    lines.apply(&mut *port).context("Failed to set DTR/RTS")?;
 
     Ok(port)
 }
 
 /// Send the IEC 62056-21 init sequence and wait for the meter to wake up.
-pub fn send_init(port: &mut dyn serialport::SerialPort) -> Result<()> {
+pub fn send_init(port: &mut dyn serialport::SerialPort, wake: Duration) -> Result<()> {
     port.write_all(IEC_INIT_SEQUENCE)?;
     port.flush()?;
-    std::thread::sleep(Duration::from_millis(500));
+    std::thread::sleep(wake);
     Ok(())
 }
 
-/// Result of a successful probe: the open port and the device ID.
+/// Check that a port can be opened and configured and that the meter answers
+/// the init sequence within `timeout`, turning each failure into a diagnostic
+/// that says what to fix. The response is left unread.
+pub fn preflight(path: &str, settings: &SerialSettings, timeout: Duration) -> Result<()> {
+    let (data_bits, parity) = settings.data_format;
+    let mut port = serialport::new(path, BAUD_RATE)
+        .data_bits(data_bits)
+        .parity(parity)
+        .stop_bits(STOP_BITS)
+        .timeout(timeout)
+        .open()
+        .map_err(|e| match e.kind() {
+            serialport::ErrorKind::NoDevice
+            | serialport::ErrorKind::Io(io::ErrorKind::NotFound) => {
+                anyhow!("{} does not exist, check --port", path)
+            }
+            serialport::ErrorKind::Io(io::ErrorKind::PermissionDenied) => anyhow!(
+                "Permission denied opening {}, add your user to the dialout group",
+                path
+            ),
+            _ => anyhow!("Failed to open serial port {}: {}", path, e),
+        })?;
+
+    settings.lines.apply(&mut *port).with_context(|| {
+        format!(
+            "{} opened but DTR/RTS could not be set, is it a serial port?",
+            path
+        )
+    })?;
+    port.set_baud_rate(BAUD_RATE)
+        .with_context(|| format!("{} does not support {} baud", path, BAUD_RATE))?;
+
+    send_init(&mut *port, settings.init_wake)
+        .with_context(|| format!("Failed to send the init sequence to {}", path))?;
+
+    let deadline = Instant::now() + timeout;
+    while port
+        .bytes_to_read()
+        .with_context(|| format!("Failed to poll {}", path))?
+        == 0
+    {
+        if Instant::now() >= deadline {
+            bail!(
+                "{} opened but no data within {}s, check IR head alignment and that the meter's optical port is enabled",
+                path,
+                timeout.as_secs()
+            );
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    }
+
+    info!("Preflight OK, {} responded", path);
+    Ok(())
+}
+
+/// Result of a successful probe: the open port, the device ID and the data
+/// format the meter answered on. The port stays at the baud rate it was
+/// found on.
 pub struct ProbeResult {
     pub port: Box<dyn serialport::SerialPort>,
     pub device_id: String,
+    pub data_format: DataFormat,
 }
 
 /// Probe a single port: send init sequence, check if first response line
-/// contains the expected device identifier. Returns the open port on match
-/// so the caller can continue reading the telegram.
-fn probe_port(path: &str, device_id: &str) -> Result<Option<ProbeResult>> {
-    debug!("Probing port {}", path);
-    let mut port = open_port(path, PROBE_TIMEOUT)?;
-    send_init(&mut *port)?;
-
-    let mut reader = BufReader::new(&mut *port);
-    let mut first_line = String::new();
-    reader.read_line(&mut first_line)?;
-
-    if first_line.contains(device_id) {
-        let found_id = first_line.trim().trim_start_matches('/').to_string();
-        info!("Found {} on port {}", found_id, path);
-        drop(reader);
+/// contains the expected device identifier. Each data format is tried in
+/// turn, starting with the one in `settings`, since a meter read with the
+/// wrong format only returns garbage. With `baud_scan` set, the other IEC
+/// baud rates are tried as well if nothing answered at 300 baud. Returns the
+/// open port on match so the caller can continue reading the telegram.
+fn probe_port(
+    path: &str,
+    device_id: &str,
+    settings: &SerialSettings,
+) -> Result<Option<ProbeResult>> {
+    let mut formats = vec![settings.data_format];
+    formats.extend(
+        DATA_FORMATS
+            .into_iter()
+            .filter(|&f| f != settings.data_format),
+    );
+
+    let mut rates = vec![BAUD_RATE];
+    if settings.baud_scan {
+        rates.extend(SCAN_BAUD_RATES);
+    }
+
+    let mut last_error = None;
+    let mut responded = false;
+    for baud_rate in rates {
+        if baud_rate != BAUD_RATE {
+            debug!(
+                "No identification at {} baud, trying {}",
+                BAUD_RATE, baud_rate
+            );
+        }
+        for &format in &formats {
+            match probe_format(path, device_id, settings, baud_rate, format) {
+                Ok(Some(result)) => return Ok(Some(result)),
+                Ok(None) => responded = true,
+                Err(e) => {
+                    debug!(
+                        "Probing {} at {} baud {:?}/{:?} failed: {}",
+                        path, baud_rate, format.0, format.1, e
+                    );
+                    last_error = Some(e);
+                }
+            }
+        }
+    }
+
+    match last_error {
+        Some(e) if !responded => Err(e),
+        _ => Ok(None),
+    }
+}
+
+/// Probe a port at one baud rate and data format.
+fn probe_format(
+    path: &str,
+    device_id: &str,
+    settings: &SerialSettings,
+    baud_rate: u32,
+    format: DataFormat,
+) -> Result<Option<ProbeResult>> {
+    debug!(
+        "Probing port {} at {} baud {:?}/{:?}",
+        path, baud_rate, format.0, format.1
+    );
+    let mut port = open_port_at(
+        path,
+        baud_rate,
+        format,
+        &settings.lines,
+        settings.probe_timeout,
+    )?;
+    send_init(&mut *port, settings.init_wake)?;
+
+    let first_line = read_ident(&mut *port)?;
+    let found_id = first_line.trim().trim_start_matches(settings.start);
+    if matches_device_id(found_id, device_id, settings.device_pattern.as_ref()) {
+        let found_id = found_id.to_string();
+        info!(
+            "Found {} on port {} ({} baud {:?}/{:?})",
+            found_id, path, baud_rate, format.0, format.1
+        );
 
         Ok(Some(ProbeResult {
             port,
             device_id: found_id,
+            data_format: format,
         }))
     } else {
         debug!(
@@ -77,41 +324,216 @@ fn probe_port(path: &str, device_id: &str) -> Result<Option<ProbeResult>> {
     }
 }
 
-/// Enumerate available serial ports, probe each ttyUSB port, and return
-/// the open port that responds with the expected device ID.
-pub fn find_meter_port(device_id: &str) -> Result<ProbeResult> {
-    let ports = serialport::available_ports().context("Failed to enumerate serial ports")?;
+/// Read the identification line a meter answers the init sequence with,
+/// ended by LF or, on some optical heads, a lone CR. Read one byte at a time,
+/// so the telegram behind it stays unread. Raw bytes are read as the wrong
+/// data format typically yields invalid UTF-8.
+fn read_ident(port: impl Read) -> io::Result<String> {
+    let mut line = Vec::new();
+    read_line(BufReader::with_capacity(1, port), &mut line)?;
+    Ok(String::from_utf8_lossy(&line).into_owned())
+}
+
+/// Whether a meter identification (`ISk5MT174-0001`, without the leading
+/// `/`) is the configured device: it contains `device_id` in any case, or
+/// matches `pattern` when one is given. Firmware updates have been seen to
+/// change the casing of the identification.
+pub fn matches_device_id(ident: &str, device_id: &str, pattern: Option<&Regex>) -> bool {
+    match pattern {
+        Some(pattern) => pattern.is_match(ident),
+        None => ident.to_lowercase().contains(&device_id.to_lowercase()),
+    }
+}
 
-    let usb_ports: Vec<_> = ports
+/// Whether a port name matches a pattern. Patterns with `*` or `?` are globs
+/// matched against the whole name (`/dev/cu.usbserial-*`), others match as a
+/// substring (`ttyACM`).
+fn matches_pattern(name: &str, pattern: &str) -> bool {
+    if !pattern.contains(['*', '?']) {
+        return name.contains(pattern);
+    }
+
+    fn glob(name: &[char], pattern: &[char]) -> bool {
+        match pattern.split_first() {
+            None => name.is_empty(),
+            Some(('*', rest)) => (0..=name.len()).any(|i| glob(&name[i..], rest)),
+            Some((&p, rest)) => name
+                .split_first()
+                .is_some_and(|(&c, name)| (p == '?' || p == c) && glob(name, rest)),
+        }
+    }
+    let name: Vec<char> = name.chars().collect();
+    let pattern: Vec<char> = pattern.chars().collect();
+    glob(&name, &pattern)
+}
+
+/// Enumerate available serial ports, probe each one matching the configured
+/// patterns in parallel, and return the first open port that responds with the
+/// expected device ID.
+pub fn find_meter_port(device_id: &str, settings: &SerialSettings) -> Result<ProbeResult> {
+    let ports = available_ports()?;
+    let patterns = settings.port_patterns.join(", ");
+
+    let candidates: Vec<_> = ports
         .iter()
-        .filter(|p| p.port_name.contains("ttyUSB"))
+        .filter(|p| {
+            settings
+                .port_patterns
+                .iter()
+                .any(|pattern| matches_pattern(&p.port_name, pattern))
+        })
         .collect();
 
-    if usb_ports.is_empty() {
-        bail!("No /dev/ttyUSB* ports found");
+    if candidates.is_empty() {
+        bail!("No serial ports matching {} found", patterns);
     }
 
     info!(
-        "Found {} USB serial port(s), probing for {}",
-        usb_ports.len(),
+        "Found {} serial port(s) matching {}, probing for {}",
+        candidates.len(),
+        patterns,
         device_id
     );
 
-    for port_info in &usb_ports {
-        match probe_port(&port_info.port_name, device_id) {
+    // Probe all ports at once, each probe can take seconds. Results that
+    // arrive after the meter was found are dropped, closing their ports.
+    let (results, received) = mpsc::channel();
+    for port_info in &candidates {
+        let results = results.clone();
+        let path = port_info.port_name.clone();
+        let (device_id, settings) = (device_id.to_string(), settings.clone());
+        std::thread::spawn(move || {
+            let result = probe_port(&path, &device_id, &settings);
+            let _ = results.send((path, result));
+        });
+    }
+    drop(results);
+
+    for (path, result) in received {
+        match result {
             Ok(Some(result)) => return Ok(result),
             Ok(None) => continue,
             Err(e) => {
-                warn!("Error probing {}: {}", port_info.port_name, e);
+                warn!("Error probing {}: {}", path, e);
                 continue;
             }
         }
     }
 
     bail!(
-        "Device {} not found on any of the {} USB serial port(s)",
+        "Device {} not found on any of the {} serial port(s) matching {}",
         device_id,
-        usb_ports.len()
+        candidates.len(),
+        patterns
     )
 }
 
+fn available_ports() -> Result<Vec<serialport::SerialPortInfo>> {
+    serialport::available_ports().context("Failed to enumerate serial ports")
+}
+
+/// One line per available serial port with its type, and for USB adapters
+/// the VID:PID, manufacturer and product, to tell the IR head apart from
+/// other USB serial devices. Ports matching `patterns` are marked with `*`.
+pub fn list_ports(patterns: &[String]) -> Result<Vec<String>> {
+    Ok(available_ports()?
+        .iter()
+        .map(|port| {
+            let probed = patterns
+                .iter()
+                .any(|pattern| matches_pattern(&port.port_name, pattern));
+            format!("{} {}", if probed { '*' } else { ' ' }, describe_port(port))
+        })
+        .collect())
+}
+
+fn describe_port(port: &serialport::SerialPortInfo) -> String {
+    let kind = match &port.port_type {
+        serialport::SerialPortType::UsbPort(usb) => {
+            let mut kind = format!("USB {:04x}:{:04x}", usb.vid, usb.pid);
+            for detail in [&usb.manufacturer, &usb.product].into_iter().flatten() {
+                kind.push_str(&format!(" {}", detail));
+            }
+            kind
+        }
+        serialport::SerialPortType::PciPort => "PCI".to_string(),
+        serialport::SerialPortType::BluetoothPort => "Bluetooth".to_string(),
+        serialport::SerialPortType::Unknown => "unknown".to_string(),
+    };
+    format!("{}  {}", port.port_name, kind)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn describe_usb_port() {
+        let port = serialport::SerialPortInfo {
+            port_name: "/dev/ttyUSB0".to_string(),
+            port_type: serialport::SerialPortType::UsbPort(serialport::UsbPortInfo {
+                vid: 0x0403,
+                pid: 0x6001,
+                serial_number: None,
+                manufacturer: Some("FTDI".to_string()),
+                product: Some("FT232R USB UART".to_string()),
+            }),
+        };
+        assert_eq!(
+            describe_port(&port),
+            "/dev/ttyUSB0  USB 0403:6001 FTDI FT232R USB UART"
+        );
+    }
+
+    #[test]
+    fn substring_patterns() {
+        assert!(matches_pattern("/dev/ttyUSB0", "ttyUSB"));
+        assert!(matches_pattern("/dev/ttyACM1", "ttyACM"));
+        assert!(!matches_pattern("/dev/ttyS0", "ttyUSB"));
+    }
+
+    #[test]
+    fn glob_patterns() {
+        assert!(matches_pattern(
+            "/dev/cu.usbserial-A10K",
+            "/dev/cu.usbserial-*"
+        ));
+        assert!(!matches_pattern(
+            "/dev/tty.usbserial-A10K",
+            "/dev/cu.usbserial-*"
+        ));
+        assert!(matches_pattern("/dev/ttyUSB3", "/dev/ttyUSB?"));
+        assert!(!matches_pattern("/dev/ttyUSB10", "/dev/ttyUSB?"));
+        assert!(matches_pattern("/dev/ttyACM0", "*ACM*"));
+    }
+
+    #[test]
+    fn ident_ends_at_lone_cr() {
+        let mut stream = &b"/ISk5MT174-0001\r1-0:1.8.0*255(0002686.675*kWh)\r"[..];
+        assert_eq!(read_ident(&mut stream).unwrap(), "/ISk5MT174-0001\r");
+        assert_eq!(stream, b"1-0:1.8.0*255(0002686.675*kWh)\r");
+
+        let mut stream = &b"/ISk5MT174-0001\r\n\r\n"[..];
+        assert_eq!(read_ident(&mut stream).unwrap(), "/ISk5MT174-0001\r");
+        assert_eq!(stream, b"\n\r\n");
+    }
+
+    #[test]
+    fn device_id_ignores_case() {
+        assert!(matches_device_id("ISk5MT174-0001", "ISk5MT174", None));
+        assert!(matches_device_id("isk5MT174-0001", "ISk5MT174", None));
+        assert!(matches_device_id("ISK5MT174-0001", "isk5mt174", None));
+        assert!(!matches_device_id("LGZ5ZMD310CR", "ISk5MT174", None));
+    }
+
+    #[test]
+    fn device_id_regex() {
+        let pattern = Regex::new(r"^(?i)isk5mt174-\d{4}$").unwrap();
+        assert!(matches_device_id("ISk5MT174-0001", "", Some(&pattern)));
+        assert!(matches_device_id("isk5MT174-0002", "", Some(&pattern)));
+        assert!(!matches_device_id("ISk5MT174-0001B", "", Some(&pattern)));
+        assert!(!matches_device_id("XISk5MT174-0001", "", Some(&pattern)));
+        // The pattern replaces the substring match
+        assert!(!matches_device_id("LGZ5ZMD310CR", "LGZ", Some(&pattern)));
+    }
+}
@@ -0,0 +1,84 @@
+use crate::probe::{DataFormat, DATA_FORMATS};
+
+/// How telegrams of one meter family look: which OBIS codes they carry,
+/// whether a BCC follows the end line, and which serial format they use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MeterProfile {
+    pub name: &'static str,
+    /// OBIS codes every telegram should contain. Missing ones are logged
+    /// when `--expected-obis` isn't given.
+    pub expected_obis: &'static [&'static str],
+    /// Whether the meter sends ETX and a BCC after the `!` line. DSMR
+    /// meters end with a CRC on the `!` line itself instead.
+    pub has_bcc: bool,
+    pub data_format: DataFormat,
+}
+
+impl MeterProfile {
+    /// Any IEC 62056-21 meter; makes no assumptions beyond the standard.
+    pub const GENERIC: Self = Self {
+        name: "generic",
+        expected_obis: &[],
+        has_bcc: true,
+        data_format: DATA_FORMATS[0],
+    };
+
+    /// Iskra MT174 and related Iskra meters read through the optical port.
+    pub const ISKRA: Self = Self {
+        name: "iskra",
+        expected_obis: &["1-0:1.8.0", "1-0:2.8.0", "1-0:32.7.0", "1-0:31.7.0"],
+        has_bcc: true,
+        data_format: DATA_FORMATS[0],
+    };
+
+    /// DSMR 4/5 smart meters read through the P1 port.
+    pub const DSMR: Self = Self {
+        name: "dsmr",
+        expected_obis: &[
+            "0-0:1.0.0",
+            "1-0:1.8.1",
+            "1-0:1.8.2",
+            "1-0:2.8.1",
+            "1-0:2.8.2",
+            "1-0:1.7.0",
+            "1-0:2.7.0",
+        ],
+        has_bcc: false,
+        data_format: DATA_FORMATS[1],
+    };
+}
+
+/// Manufacturer prefixes of DSMR P1 identification lines, e.g.
+/// `/KFM5KAIFA-METER` or `/XMX5LGBBFG1012463957`.
+const DSMR_VENDORS: &[&str] = &["KFM", "XMX", "Ene", "KMP", "SAG", "FLU"];
+
+/// Select the parsing profile for a meter from its identification line
+/// (with or without the leading `/`). Unknown meters get the generic profile.
+pub fn detect_meter_profile(ident: &str) -> MeterProfile {
+    let ident = ident.trim().trim_start_matches('/');
+    // DSMR idents carry the `\2` mode marker after the vendor and baud char
+    if ident.contains('\\') || DSMR_VENDORS.iter().any(|v| ident.starts_with(v)) {
+        MeterProfile::DSMR
+    } else if ident.starts_with("ISk") || ident.starts_with("ISK") {
+        MeterProfile::ISKRA
+    } else {
+        MeterProfile::GENERIC
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_known_meters() {
+        assert_eq!(detect_meter_profile("/ISk5MT174-0001"), MeterProfile::ISKRA);
+        assert_eq!(detect_meter_profile("ISk5MT174"), MeterProfile::ISKRA);
+        assert_eq!(
+            detect_meter_profile("/ISk5\\2MT382-1000"),
+            MeterProfile::DSMR
+        );
+        assert_eq!(detect_meter_profile("/KFM5KAIFA-METER"), MeterProfile::DSMR);
+        assert_eq!(detect_meter_profile("/LGZ5ZMD310CR"), MeterProfile::GENERIC);
+    }
+}
@@ -0,0 +1,237 @@
+use anyhow::{Context, Result};
+use log::{debug, info, warn};
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::meter::MeterReading;
+
+/// State shared between the read loop and the metrics endpoint.
+#[derive(Debug, Default)]
+pub struct MetricsState {
+    /// The most recent successful reading per device ID.
+    pub readings: BTreeMap<String, MeterReading>,
+    pub reads_total: u64,
+    pub read_errors_total: u64,
+    /// Whether the most recent read attempt succeeded.
+    pub last_read_ok: bool,
+    /// Malformed lines across all telegrams read.
+    pub malformed_lines_total: u64,
+    /// Time spent reading the telegrams counted in `reads_total`.
+    pub read_time_total: Duration,
+    /// Duration of the most recent successful read.
+    pub last_read_time: Duration,
+    /// Reconnects to the meter after errors or the watchdog fired.
+    pub reconnects_total: u64,
+}
+
+impl MetricsState {
+    /// One line summing up how reading went, to judge the IR head alignment.
+    pub fn summary(&self) -> String {
+        let average = self
+            .read_time_total
+            .checked_div(self.reads_total.try_into().unwrap_or(u32::MAX))
+            .unwrap_or_default();
+        format!(
+            "{} telegrams read, {} read errors, {} malformed lines, {:.2}s average read time",
+            self.reads_total,
+            self.read_errors_total,
+            self.malformed_lines_total,
+            average.as_secs_f64()
+        )
+    }
+}
+
+/// Bind the metrics listener and serve it from a background thread.
+pub fn serve(addr: SocketAddr, state: Arc<Mutex<MetricsState>>) -> Result<()> {
+    let listener = TcpListener::bind(addr)
+        .with_context(|| format!("Failed to bind Prometheus listener on {}", addr))?;
+    info!("Serving Prometheus metrics on http://{}/metrics", addr);
+
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    if let Err(e) = handle(stream, &state) {
+                        debug!("Metrics request failed: {}", e);
+                    }
+                }
+                Err(e) => warn!("Failed to accept metrics connection: {}", e),
+            }
+        }
+    });
+
+    Ok(())
+}
+
+/// Answer a single HTTP request. Only `GET /metrics` is supported.
+fn handle(mut stream: TcpStream, state: &Mutex<MetricsState>) -> Result<()> {
+    let mut reader = BufReader::new(&stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+
+    // Drain the request headers
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let response = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/metrics")) => {
+            let body = render(&state.lock().expect("metrics lock poisoned"));
+            format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            )
+        }
+        _ => "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string(),
+    };
+
+    stream.write_all(response.as_bytes())?;
+    Ok(())
+}
+
+/// Render the state in the Prometheus text exposition format. Every numeric
+/// reading field becomes a gauge labelled with the device ID.
+fn render(state: &MetricsState) -> String {
+    let mut out = String::new();
+
+    out.push_str("# TYPE energymon_reads_total counter\n");
+    let _ = writeln!(out, "energymon_reads_total {}", state.reads_total);
+    out.push_str("# TYPE energymon_read_errors_total counter\n");
+    let _ = writeln!(
+        out,
+        "energymon_read_errors_total {}",
+        state.read_errors_total
+    );
+
+    let devices: Vec<_> = state
+        .readings
+        .values()
+        .filter_map(|reading| match serde_json::to_value(reading) {
+            Ok(serde_json::Value::Object(fields)) => {
+                Some((escape_label(&reading.device_id), fields))
+            }
+            _ => None,
+        })
+        .collect();
+    let Some((_, first)) = devices.first() else {
+        return out;
+    };
+
+    // Samples of one metric must be grouped under a single TYPE line
+    for name in first.keys() {
+        let mut typed = false;
+        for (device, fields) in &devices {
+            let Some(value) = fields.get(name).and_then(|v| v.as_f64()) else {
+                continue;
+            };
+            if !typed {
+                let _ = writeln!(out, "# TYPE energymon_{} gauge", name);
+                typed = true;
+            }
+            let _ = writeln!(out, "energymon_{}{{device=\"{}\"}} {}", name, device, value);
+        }
+    }
+
+    out
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_without_reading() {
+        let state = MetricsState {
+            read_errors_total: 3,
+            ..Default::default()
+        };
+        let out = render(&state);
+        assert!(out.contains("energymon_reads_total 0\n"));
+        assert!(out.contains("energymon_read_errors_total 3\n"));
+        assert!(!out.contains("energymon_consumption_total_kwh"));
+    }
+
+    #[test]
+    fn render_reading_gauges() {
+        let reading = MeterReading {
+            device_id: "ISk5MT174-0001".to_string(),
+            consumption_total_kwh: 2686.675,
+            ..Default::default()
+        };
+        let state = MetricsState {
+            readings: BTreeMap::from([(reading.device_id.clone(), reading)]),
+            reads_total: 1,
+            ..Default::default()
+        };
+        let out = render(&state);
+        assert!(out.contains("# TYPE energymon_consumption_total_kwh gauge\n"));
+        assert!(
+            out.contains("energymon_consumption_total_kwh{device=\"ISk5MT174-0001\"} 2686.675\n")
+        );
+        assert!(!out.contains("energymon_timestamp"));
+    }
+
+    #[test]
+    fn render_multiple_devices() {
+        let readings = ["ISk5MT174-0001", "ISk5MT174-0002"].map(|id| MeterReading {
+            device_id: id.to_string(),
+            consumption_total_kwh: 1.5,
+            ..Default::default()
+        });
+        let state = MetricsState {
+            readings: readings
+                .into_iter()
+                .map(|r| (r.device_id.clone(), r))
+                .collect(),
+            ..Default::default()
+        };
+        let out = render(&state);
+        assert_eq!(
+            out.matches("# TYPE energymon_consumption_total_kwh gauge\n")
+                .count(),
+            1
+        );
+        assert!(out.contains(
+            "energymon_consumption_total_kwh{device=\"ISk5MT174-0001\"} 1.5\n\
+             energymon_consumption_total_kwh{device=\"ISk5MT174-0002\"} 1.5\n"
+        ));
+    }
+
+    #[test]
+    fn label_escaping() {
+        assert_eq!(escape_label("a\"b\\c"), "a\\\"b\\\\c");
+    }
+
+    #[test]
+    fn summary_line() {
+        let mut state = MetricsState::default();
+        assert_eq!(
+            state.summary(),
+            "0 telegrams read, 0 read errors, 0 malformed lines, 0.00s average read time"
+        );
+        state.reads_total = 4;
+        state.read_errors_total = 1;
+        state.malformed_lines_total = 3;
+        state.read_time_total = Duration::from_millis(5000);
+        assert_eq!(
+            state.summary(),
+            "4 telegrams read, 1 read errors, 3 malformed lines, 1.25s average read time"
+        );
+    }
+}
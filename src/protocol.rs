@@ -1,108 +1,687 @@
 use anyhow::{bail, Context, Result};
-use chrono::Local;
-use log::{debug, info};
-use std::io::{BufRead, BufReader};
+use chrono::{DateTime, FixedOffset, Local, LocalResult, NaiveDateTime, TimeZone};
+use chrono_tz::Tz;
+use log::{debug, info, warn};
+use regex::Regex;
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::path::{Path, PathBuf};
 use std::time::Duration;
 
-use crate::meter::MeterReading;
-use crate::probe::{open_port, send_init};
+use crate::hexdump::HexDump;
+use crate::meter::{MeterReading, ObisValue, PowerSettings};
+use crate::mock::MockMeter;
+use crate::obis::ObisMap;
+use crate::probe::{
+    find_meter_port, matches_device_id, open_port, open_port_at, send_init, ProbeResult,
+    SerialSettings,
+};
+use crate::profile::{detect_meter_profile, MeterProfile};
 
-/// Holds an open serial connection to a meter for repeated readings.
+/// Start of text — precedes the data block in Mode C telegrams.
+pub const STX: u8 = 0x02;
+/// End of text — follows the `!` line and is followed by the BCC.
+pub const ETX: u8 = 0x03;
+
+/// IEC 62056-21 protocol mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum Mode {
+    /// Request each telegram with the `/?!` init sequence.
+    #[default]
+    C,
+    /// Listen for telegrams the meter pushes on its own at a fixed baud rate.
+    D,
+}
+
+/// Checksum carried on the `!` line of a telegram.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum CrcCheck {
+    /// DSMR P1: CRC16 (polynomial 0xA001) from `/` through `!`, as 4 hex digits.
+    Dsmr,
+}
+
+/// Options controlling how telegrams are read and validated.
+#[derive(Debug, Clone)]
+pub struct TelegramOptions {
+    /// Read the ETX/BCC trailer and reject telegrams whose BCC doesn't match.
+    pub verify_bcc: bool,
+    /// Reject telegrams whose checksum after `!` doesn't match.
+    pub crc_check: Option<CrcCheck>,
+    /// Append every complete telegram, as received, to this file.
+    pub raw_log: Option<PathBuf>,
+    /// Fail on the first telegram from another device instead of skipping it.
+    pub strict_device_id: bool,
+    /// Identification the meter must match instead of containing the
+    /// device ID, see `matches_device_id`.
+    pub device_pattern: Option<Regex>,
+    /// OBIS codes the telegram should contain; others, and missing ones, are
+    /// logged as warnings. Empty disables the check.
+    pub expected_obis: Vec<String>,
+    /// Give up on a telegram that hasn't ended after this many lines.
+    pub max_lines: usize,
+    /// Which OBIS code fills which reading field.
+    pub obis_map: ObisMap,
+    /// How power values are calculated from the phase readings.
+    pub power: PowerSettings,
+    /// Log every byte received as a hex dump before it is parsed.
+    pub hexdump: bool,
+    /// Timezone the meter clock runs in; the host's when None.
+    pub meter_tz: Option<Tz>,
+    /// First character of the identification line, `/` in IEC 62056-21.
+    pub start: char,
+    /// First character of the end line, `!` in IEC 62056-21.
+    pub end: char,
+}
+
+impl Default for TelegramOptions {
+    fn default() -> Self {
+        Self {
+            verify_bcc: false,
+            crc_check: None,
+            raw_log: None,
+            strict_device_id: false,
+            device_pattern: None,
+            expected_obis: Vec::new(),
+            max_lines: DEFAULT_MAX_TELEGRAM_LINES,
+            obis_map: ObisMap::default(),
+            power: PowerSettings::default(),
+            hexdump: false,
+            meter_tz: None,
+            start: '/',
+            end: '!',
+        }
+    }
+}
+
+/// Default line limit per telegram, well above the ~50 lines of a DSMR 5 telegram.
+pub const DEFAULT_MAX_TELEGRAM_LINES: usize = 200;
+
+/// Longest line accepted in a telegram. Real OBIS lines are under 100 bytes,
+/// longer ones only come from noise on the line.
+const MAX_LINE_BYTES: u64 = 1024;
+
+/// Telegrams from other devices skipped in one read before giving up.
+const MAX_FOREIGN_TELEGRAMS: usize = 5;
+
+/// Where telegrams are read from.
+enum TelegramSource {
+    /// A serial port driven with the IEC 62056-21 init sequence.
+    Serial(Box<dyn serialport::SerialPort>),
+    /// A pre-recorded or bridged stream (file, TCP) that already carries
+    /// complete telegrams, so no init or baud negotiation is needed.
+    Reader(Box<dyn BufRead + Send>),
+    /// A simulated meter, for development without hardware.
+    Mock(MockMeter),
+    /// The previous source was dropped and reopening it failed.
+    Disconnected,
+}
+
+/// How a connection was established, so `reconnect` can do it again.
+enum Origin {
+    /// An explicit serial port path.
+    Port { path: String, timeout: Duration },
+    /// A serial port a Mode D meter pushes telegrams on.
+    Push {
+        path: String,
+        baud_rate: u32,
+        timeout: Duration,
+    },
+    /// A serial port found by probing for the device ID.
+    Probe,
+    /// A file of captured telegrams.
+    File(PathBuf),
+    /// A `host:port` stream of telegrams (e.g. ser2net).
+    Tcp { addr: String, timeout: Duration },
+    /// A simulated meter.
+    Simulated,
+    /// A caller-supplied reader, which can't be reopened.
+    Reader,
+}
+
+/// Holds an open connection to a meter for repeated readings.
 pub struct MeterConnection {
-    port: Box<dyn serialport::SerialPort>,
+    source: TelegramSource,
+    origin: Origin,
     device_id: String,
-    /// Whether the first telegram is already in progress (from probing).
-    first_read_primed: bool,
+    serial: SerialSettings,
+    options: TelegramOptions,
+    /// Parsing profile, detected from the configured device ID until the
+    /// meter's identification line has been read.
+    profile: MeterProfile,
+    /// Identification line of a telegram already in progress, read while
+    /// probing or verifying the port.
+    primed_ident: Option<String>,
+    /// Whether the meter is still streaming from the last init, so with
+    /// `--persistent-session` the next telegram can be read without one.
+    session_open: bool,
 }
 
 impl MeterConnection {
     /// Open a fresh connection and send the first init sequence.
-    pub fn open(port_path: &str, device_id: &str, timeout: Duration) -> Result<Self> {
-        info!("Opening {} for meter reading", port_path);
-        let mut port = open_port(port_path, timeout)?;
-        send_init(&mut *port)?;
-        Ok(Self {
-            port,
-            device_id: device_id.to_string(),
-            first_read_primed: false,
-        })
+    pub fn open(
+        port_path: &str,
+        device_id: &str,
+        timeout: Duration,
+        serial: SerialSettings,
+        options: TelegramOptions,
+    ) -> Result<Self> {
+        let origin = Origin::Port {
+            path: port_path.to_string(),
+            timeout,
+        };
+        Self::connect(origin, device_id, serial, options)
     }
 
-    /// Create from a port that was already initialized by the probe.
+    /// Create from a port that was already initialized by the probe, keeping
+    /// the data format it answered on.
     /// The device ID line was already consumed during probing.
-    pub fn from_probe(port: Box<dyn serialport::SerialPort>, device_id: &str) -> Self {
+    pub fn from_probe(
+        result: ProbeResult,
+        mut serial: SerialSettings,
+        options: TelegramOptions,
+    ) -> Self {
+        // Re-probing tries the format that worked first
+        serial.data_format = result.data_format;
         Self {
-            port,
+            source: TelegramSource::Serial(result.port),
+            origin: Origin::Probe,
+            profile: detect_meter_profile(&result.device_id),
+            primed_ident: Some(result.device_id.clone()),
+            device_id: result.device_id,
+            serial,
+            options,
+            session_open: false,
+        }
+    }
+
+    /// Listen on a serial port for telegrams pushed by a Mode D meter. No init
+    /// sequence is sent; the port stays at the given fixed baud rate.
+    pub fn open_mode_d(
+        port_path: &str,
+        device_id: &str,
+        baud_rate: u32,
+        timeout: Duration,
+        serial: SerialSettings,
+        options: TelegramOptions,
+    ) -> Result<Self> {
+        let origin = Origin::Push {
+            path: port_path.to_string(),
+            baud_rate,
+            timeout,
+        };
+        Self::connect(origin, device_id, serial, options)
+    }
+
+    /// Read telegrams from a file of captured telegrams.
+    pub fn open_file(path: &Path, device_id: &str, options: TelegramOptions) -> Result<Self> {
+        let origin = Origin::File(path.to_path_buf());
+        Self::connect(origin, device_id, SerialSettings::default(), options)
+    }
+
+    /// Read telegrams from a TCP stream such as a ser2net bridge.
+    pub fn open_tcp(
+        addr: &str,
+        device_id: &str,
+        timeout: Duration,
+        options: TelegramOptions,
+    ) -> Result<Self> {
+        let origin = Origin::Tcp {
+            addr: addr.to_string(),
+            timeout,
+        };
+        Self::connect(origin, device_id, SerialSettings::default(), options)
+    }
+
+    /// Read synthetic telegrams from a simulated meter.
+    pub fn simulate(device_id: &str, options: TelegramOptions) -> Result<Self> {
+        Self::connect(
+            Origin::Simulated,
+            device_id,
+            SerialSettings::default(),
+            options,
+        )
+    }
+
+    /// Create from an arbitrary reader that yields complete telegrams, such as
+    /// a captured telegram file or a ser2net TCP stream. Skips init and baud
+    /// negotiation entirely.
+    pub fn from_reader(
+        reader: impl BufRead + Send + 'static,
+        device_id: &str,
+        options: TelegramOptions,
+    ) -> Self {
+        Self {
+            source: TelegramSource::Reader(Box::new(reader)),
+            origin: Origin::Reader,
+            device_id: device_id.to_string(),
+            serial: SerialSettings::default(),
+            options,
+            profile: detect_meter_profile(device_id),
+            primed_ident: None,
+            session_open: false,
+        }
+    }
+
+    fn connect(
+        origin: Origin,
+        device_id: &str,
+        serial: SerialSettings,
+        options: TelegramOptions,
+    ) -> Result<Self> {
+        let (source, primed_ident) = open_origin(&origin, device_id, &serial)?;
+        Ok(Self {
+            source,
+            origin,
             device_id: device_id.to_string(),
-            first_read_primed: true,
+            serial,
+            options,
+            profile: detect_meter_profile(device_id),
+            primed_ident,
+            session_open: false,
+        })
+    }
+
+    /// Drop the current handle and reopen the connection the same way it was
+    /// first established (reopening the port, re-probing, or reconnecting the
+    /// stream). If reopening fails the connection stays disconnected and
+    /// `read` errors until a later `reconnect` succeeds.
+    pub fn reconnect(&mut self) -> Result<()> {
+        info!("Reconnecting to meter");
+
+        // Release the old handle first, serial ports are opened exclusively
+        self.source = TelegramSource::Disconnected;
+        self.primed_ident = None;
+        self.session_open = false;
+
+        let (source, primed_ident) = open_origin(&self.origin, &self.device_id, &self.serial)?;
+        self.source = source;
+        self.primed_ident = primed_ident;
+        Ok(())
+    }
+
+    /// Path and baud rate of the serial port being read, if any. For a
+    /// probed port that is the one that answered, at the rate it answered on.
+    pub fn serial_port(&self) -> Option<(String, u32)> {
+        match (&self.source, &self.origin) {
+            (TelegramSource::Disconnected, _) => None,
+            (
+                _,
+                Origin::Push {
+                    path, baud_rate, ..
+                },
+            ) => Some((path.clone(), *baud_rate)),
+            (TelegramSource::Serial(port), _) => Some((port.name()?, port.baud_rate().ok()?)),
+            _ => None,
         }
     }
 
     /// Read one telegram from the meter. On subsequent calls, switches back
     /// to 300 baud, sends a new init sequence, negotiates baud rate, then
-    /// reads the telegram at the higher baud rate.
-    pub fn read(&mut self, interval: Duration) -> Result<MeterReading> {
-        if self.first_read_primed {
-            self.first_read_primed = false;
+    /// reads the telegram at the higher baud rate. In a persistent session
+    /// the init is only repeated after a failed read.
+    /// Reader sources simply yield their next telegram.
+    pub fn read(&mut self) -> Result<MeterReading> {
+        let reading = self.read_source()?;
+        let profile = detect_meter_profile(&reading.device_id);
+        if profile != self.profile {
+            info!(
+                "Detected {} meter profile for {}",
+                profile.name, reading.device_id
+            );
+            self.profile = profile;
+        }
+        Ok(reading)
+    }
+
+    fn read_source(&mut self) -> Result<MeterReading> {
+        if let Some(ident) = self.primed_ident.take() {
             info!("Reading first telegram (already primed)");
-            let reader = BufReader::new(&mut *self.port);
-            read_telegram(reader, &self.device_id, true)
-        } else {
-            // Wait for the configured interval before the next request
-            std::thread::sleep(interval);
+            let TelegramSource::Serial(port) = &mut self.source else {
+                unreachable!("only serial connections are primed");
+            };
+            let reader = serial_reader(&mut **port, self.serial.persistent_session);
+            let result = read_telegram(reader, &ident, true, &self.options, &self.profile);
+            self.session_open = result.is_ok();
+            return result;
+        }
 
-            // Discard any stray bytes left in the serial buffer
-            self.port
-                .clear(serialport::ClearBuffer::Input)
-                .context("Failed to clear serial input buffer")?;
+        match &mut self.source {
+            TelegramSource::Serial(port) => {
+                if self.serial.persistent_session && self.session_open {
+                    debug!("Reading next telegram of the persistent session");
+                } else {
+                    // Discard any stray bytes left in the serial buffer
+                    port.clear(serialport::ClearBuffer::Input)
+                        .context("Failed to clear serial input buffer")?;
 
-            info!("Sending init sequence for new reading");
-            send_init(&mut *self.port)?;
+                    info!("Sending init sequence for new reading");
+                    send_init(&mut **port, self.serial.init_wake)?;
+                }
 
-            let reader = BufReader::new(&mut *self.port);
-            read_telegram(reader, &self.device_id, false)
+                let reader = serial_reader(&mut **port, self.serial.persistent_session);
+                let result =
+                    read_telegram(reader, &self.device_id, false, &self.options, &self.profile);
+                // A timeout or garbled telegram falls back to a fresh init
+                self.session_open = result.is_ok();
+                result
+            }
+            TelegramSource::Reader(reader) => {
+                read_telegram(reader, &self.device_id, false, &self.options, &self.profile)
+            }
+            TelegramSource::Mock(meter) => Ok(meter.read(&self.options.power)),
+            TelegramSource::Disconnected => {
+                Err(io::Error::new(io::ErrorKind::NotConnected, "meter is not connected").into())
+            }
         }
     }
 }
 
+/// Buffer a serial port for reading one telegram. In a persistent session
+/// the meter keeps sending, and whatever a larger buffer read past the end of
+/// this telegram would be dropped with the reader, so bytes are taken one at
+/// a time instead.
+fn serial_reader<R: Read>(port: R, persistent_session: bool) -> BufReader<R> {
+    if persistent_session {
+        BufReader::with_capacity(1, port)
+    } else {
+        BufReader::new(port)
+    }
+}
+
+/// Open the source described by `origin`. Also returns the identification
+/// line if it was already consumed, by probing or by verifying the port.
+fn open_origin(
+    origin: &Origin,
+    device_id: &str,
+    serial: &SerialSettings,
+) -> Result<(TelegramSource, Option<String>)> {
+    match origin {
+        Origin::Port { path, timeout } => {
+            info!("Opening {} for meter reading", path);
+            let mut port = open_port(path, serial.data_format, &serial.lines, *timeout)?;
+            send_init(&mut *port, serial.init_wake)?;
+            if !serial.verify_ident {
+                return Ok((TelegramSource::Serial(port), None));
+            }
+            // One byte at a time, the telegram behind the line must stay
+            // unread. The line may end in a lone CR, the LF of a CRLF is
+            // left for `read_telegram` to skip
+            let mut line = Vec::new();
+            read_line(serial_reader(&mut *port, true), &mut line)
+                .with_context(|| format!("Failed to read identification from {}", path))?;
+            let ident = check_ident(
+                &line,
+                serial.start,
+                device_id,
+                serial.device_pattern.as_ref(),
+            )
+            .with_context(|| format!("Wrong meter on {}", path))?;
+            Ok((TelegramSource::Serial(port), Some(ident)))
+        }
+        Origin::Push {
+            path,
+            baud_rate,
+            timeout,
+        } => {
+            info!("Listening on {} at {} baud (Mode D)", path, baud_rate);
+            let port = open_port_at(
+                path,
+                *baud_rate,
+                serial.data_format,
+                &serial.lines,
+                *timeout,
+            )?;
+            // Keep one buffered reader so bytes of the next pushed telegram
+            // aren't lost between reads
+            Ok((TelegramSource::Reader(Box::new(BufReader::new(port))), None))
+        }
+        Origin::Probe => {
+            let result = find_meter_port(device_id, serial)?;
+            Ok((TelegramSource::Serial(result.port), Some(result.device_id)))
+        }
+        Origin::File(path) => {
+            info!("Reading telegrams from file: {}", path.display());
+            let file =
+                File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+            Ok((TelegramSource::Reader(Box::new(BufReader::new(file))), None))
+        }
+        Origin::Tcp { addr, timeout } => {
+            info!("Reading telegrams from tcp://{}", addr);
+            let stream = TcpStream::connect(addr)
+                .with_context(|| format!("Failed to connect to {}", addr))?;
+            stream.set_read_timeout(Some(*timeout))?;
+            Ok((
+                TelegramSource::Reader(Box::new(BufReader::new(stream))),
+                None,
+            ))
+        }
+        Origin::Simulated => {
+            info!("Simulating meter {}", device_id);
+            Ok((TelegramSource::Mock(MockMeter::new(device_id)), None))
+        }
+        Origin::Reader => bail!("Cannot reopen a caller-supplied reader"),
+    }
+}
+
+/// Check the identification line a meter answered the init sequence with
+/// against the configured device ID, returning it without the leading
+/// `start` character.
+fn check_ident(
+    line: &[u8],
+    start: char,
+    device_id: &str,
+    pattern: Option<&Regex>,
+) -> Result<String> {
+    let line = String::from_utf8_lossy(line);
+    let ident = line.trim().trim_start_matches(start);
+    if !matches_device_id(ident, device_id, pattern) {
+        bail!(
+            "meter identifies as {:?}, not {} (--no-verify reads it anyway)",
+            ident,
+            pattern.map_or(device_id, Regex::as_str)
+        );
+    }
+    Ok(ident.to_string())
+}
+
+// Errno values reported when a USB serial adapter is unplugged.
+const EIO: i32 = 5;
+const ENXIO: i32 = 6;
+const ENODEV: i32 = 19;
+
+/// Whether an error means the underlying device or stream is gone, so
+/// retrying on the same handle is pointless and a reconnect is needed.
+pub fn is_disconnect(err: &anyhow::Error) -> bool {
+    err.chain().any(|cause| {
+        if let Some(e) = cause.downcast_ref::<io::Error>() {
+            return matches!(
+                e.kind(),
+                io::ErrorKind::NotFound
+                    | io::ErrorKind::NotConnected
+                    | io::ErrorKind::BrokenPipe
+                    | io::ErrorKind::ConnectionReset
+                    | io::ErrorKind::ConnectionAborted
+            ) || matches!(e.raw_os_error(), Some(EIO | ENXIO | ENODEV));
+        }
+        if let Some(e) = cause.downcast_ref::<serialport::Error>() {
+            return matches!(
+                e.kind(),
+                serialport::ErrorKind::NoDevice
+                    | serialport::ErrorKind::Io(io::ErrorKind::NotFound)
+            );
+        }
+        false
+    })
+}
+
 /// Read and parse the meter telegram from a BufReader.
 /// If `device_id_consumed` is true, the device ID line was already read (e.g. during probing).
-fn read_telegram(
-    mut reader: impl BufRead,
+/// The `profile` tells whether a BCC follows the `!` line and which OBIS
+/// codes to expect when `options` doesn't list any.
+pub fn read_telegram(
+    reader: impl BufRead,
     device_id: &str,
     device_id_consumed: bool,
+    options: &TelegramOptions,
+    profile: &MeterProfile,
 ) -> Result<MeterReading> {
+    let mut reader = HexDump::new(reader, options.hexdump);
     let mut reading = MeterReading::default();
+    // Raw bytes of the data block, used for BCC verification
+    let mut frame: Vec<u8> = Vec::new();
+    // Everything received including the identification line, for --raw-log
+    let mut raw: Vec<u8> = Vec::new();
+    // Telegrams from other devices on a shared bus, skipped unless strict
+    let mut foreign = 0;
+    let mut skipping = false;
+    // OBIS codes present in the telegram, for --expected-obis
+    let mut seen = BTreeSet::new();
+    let mut lines = 0;
+    // Set once a line ended in a CR without LF, as some optical heads send
+    let mut cr_only = false;
 
     if device_id_consumed {
         reading.device_id = device_id.to_string();
     }
 
     loop {
-        let mut line = String::new();
-        let bytes_read = reader
-            .read_line(&mut line)
+        // Some meters end the data block with ETX + BCC instead of a `!` line
+        let next = reader
+            .fill_buf()
+            .context("Failed to read from serial port")?
+            .first()
+            .copied();
+        if next == Some(ETX) {
+            let mut trailer = [0u8; 2];
+            reader
+                .read_exact(&mut trailer)
+                .context("Failed to read ETX/BCC at end of telegram")?;
+            if reading.device_id.is_empty() {
+                // Trailer left over from a telegram read without --verify-bcc,
+                // or the end of a skipped telegram
+                skipping = false;
+                continue;
+            }
+            frame.extend_from_slice(&trailer);
+            raw.extend_from_slice(&trailer);
+            finish_telegram(&raw, &frame, options.verify_bcc, options)?;
+            break;
+        }
+
+        lines += 1;
+        if lines > options.max_lines {
+            bail!(
+                "No end of telegram after {} lines, is the line noisy?",
+                options.max_lines
+            );
+        }
+
+        let mut bytes = Vec::new();
+        let bytes_read = read_line((&mut reader).take(MAX_LINE_BYTES + 1), &mut bytes)
             .context("Failed to read line from serial port")?;
 
         if bytes_read == 0 {
             bail!("Serial port returned EOF before complete telegram");
         }
+        if bytes_read as u64 > MAX_LINE_BYTES {
+            bail!("Telegram line longer than {} bytes", MAX_LINE_BYTES);
+        }
+
+        // The LF of a CRLF identification line the caller read up to its CR
+        if device_id_consumed && lines == 1 && bytes == b"\n" {
+            continue;
+        }
+
+        // Take the LF of a CRLF along with its line. A CR-only meter may send
+        // nothing after its end line, so don't wait for a byte there
+        let last = cr_only && trim_line(&String::from_utf8_lossy(&bytes)).starts_with(options.end);
+        if bytes.ends_with(b"\r") && !last {
+            match reader
+                .fill_buf()
+                .context("Failed to read line from serial port")?
+                .first()
+            {
+                Some(b'\n') => {
+                    reader.consume(1);
+                    bytes.push(b'\n');
+                }
+                _ => cr_only = true,
+            }
+        }
 
-        let trimmed = line.trim();
+        // Noise on the line can inject bytes that aren't valid UTF-8; such a
+        // line is skipped below instead of failing the whole telegram
+        let garbled = std::str::from_utf8(&bytes).is_err();
+        let line = String::from_utf8_lossy(&bytes);
+
+        let trimmed = trim_line(&line);
         debug!("Serial: {}", trimmed);
+        raw.extend_from_slice(&bytes);
 
         // Device identification line (e.g. "/ISk5MT174-0001")
-        if trimmed.starts_with('/') {
-            if trimmed.contains(device_id) {
-                reading.device_id = trimmed.trim_start_matches('/').to_string();
+        if trimmed.starts_with(options.start) {
+            let ident = trimmed.trim_start_matches(options.start);
+            if matches_device_id(ident, device_id, options.device_pattern.as_ref()) {
+                if !reading.device_id.is_empty() {
+                    // The meter restarted before finishing the telegram; drop
+                    // what was read so two telegrams don't get mixed
+                    warn!("Telegram restarted by {} before its end", trimmed);
+                    reading = MeterReading::default();
+                    frame.clear();
+                    raw = bytes.clone();
+                    seen.clear();
+                }
+                reading.device_id = ident.to_string();
+                skipping = false;
             } else if !device_id_consumed {
-                bail!("Unexpected device: {}", trimmed);
+                if options.strict_device_id {
+                    bail!("Unexpected device: {}", trimmed);
+                }
+                foreign += 1;
+                if foreign > MAX_FOREIGN_TELEGRAMS {
+                    bail!(
+                        "Skipped {} telegrams from other devices, last: {}",
+                        MAX_FOREIGN_TELEGRAMS,
+                        trimmed
+                    );
+                }
+                warn!("Skipping telegram from unexpected device: {}", trimmed);
+                skipping = true;
+                frame.clear();
+                raw.clear();
+            }
+            continue;
+        }
+
+        if skipping {
+            raw.clear();
+            if trimmed.starts_with(options.end) {
+                skipping = false;
             }
             continue;
         }
 
-        // End of telegram
-        if trimmed.starts_with('!') {
+        frame.extend_from_slice(&bytes);
+
+        // End of telegram, possibly followed by a checksum
+        if let Some(crc) = trimmed.strip_prefix(options.end) {
+            reading.telegram_crc = crc.trim().to_string();
+            // Meters without a BCC would leave us waiting for the trailer
+            let verify = options.verify_bcc && profile.has_bcc;
+            if verify {
+                let mut trailer = [0u8; 2];
+                reader
+                    .read_exact(&mut trailer)
+                    .context("Failed to read ETX/BCC after end of telegram")?;
+                frame.extend_from_slice(&trailer);
+                raw.extend_from_slice(&trailer);
+            }
+            finish_telegram(&raw, &frame, verify, options)?;
+            if options.crc_check == Some(CrcCheck::Dsmr) {
+                verify_dsmr_crc(&raw, options.start, options.end)?;
+            }
             break;
         }
 
@@ -110,125 +689,372 @@ fn read_telegram(
             continue;
         }
 
-        parse_obis_line(trimmed, &mut reading);
+        if garbled {
+            warn!("Skipping line with invalid UTF-8: {:?}", trimmed);
+            reading.malformed_line_count += 1;
+            continue;
+        }
+
+        match parse_obis_line(trimmed, &mut reading, &options.obis_map, options.meter_tz) {
+            Some(code) => {
+                seen.insert(code.to_string());
+            }
+            None => {
+                debug!("Malformed line: {:?}", trimmed);
+                reading.malformed_line_count += 1;
+            }
+        }
+    }
+
+    if !options.expected_obis.is_empty() {
+        let (unexpected, missing) = compare_obis(&seen, &options.expected_obis);
+        if !unexpected.is_empty() {
+            warn!(
+                "Telegram contains unexpected OBIS codes: {}",
+                unexpected.join(", ")
+            );
+        }
+        if !missing.is_empty() {
+            warn!(
+                "Telegram is missing expected OBIS codes: {}",
+                missing.join(", ")
+            );
+        }
+    } else if !reading.device_id.is_empty() {
+        // A profile only lists the codes every meter of the family sends,
+        // so extra codes are fine
+        let (_, missing) = compare_obis(&seen, profile.expected_obis);
+        if !missing.is_empty() {
+            warn!(
+                "Telegram is missing OBIS codes expected for {} meters: {}",
+                profile.name,
+                missing.join(", ")
+            );
+        }
     }
 
     if reading.device_id.is_empty() {
         bail!("Never received device identification line");
     }
 
-    reading.calculate_power();
+    reading.calculate_power(&options.power);
     reading.timestamp = Local::now().format("%Y-%m-%d %H:%M:%S%.6f").to_string();
 
     info!("Reading complete: {:?}", reading);
     Ok(reading)
 }
 
-/// Parse a single OBIS data line like `1-0:1.8.0(0011404.409*kWh)` and
-/// populate the corresponding field in MeterReading.
-fn parse_obis_line(line: &str, reading: &mut MeterReading) {
-    let (raw_code, raw_value) = match (line.find('('), line.find(')')) {
-        (Some(open), Some(close)) if open < close => (&line[..open], &line[open + 1..close]),
-        _ => return,
+/// Read one line into `buf`, up to and including its `\n` or `\r`. Unlike
+/// `read_until`, a line also ends at a lone CR.
+pub(crate) fn read_line(mut reader: impl BufRead, buf: &mut Vec<u8>) -> io::Result<usize> {
+    let start = buf.len();
+    loop {
+        let available = match reader.fill_buf() {
+            Ok(available) => available,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        let (done, used) = match available.iter().position(|&b| b == b'\n' || b == b'\r') {
+            Some(pos) => (true, pos + 1),
+            None => (available.is_empty(), available.len()),
+        };
+        buf.extend_from_slice(&available[..used]);
+        reader.consume(used);
+        if done {
+            return Ok(buf.len() - start);
+        }
+    }
+}
+
+/// A telegram line without its line ending and surrounding whitespace. The
+/// first data line may be prefixed with STX.
+fn trim_line(line: &str) -> &str {
+    line.trim().trim_start_matches(char::from(STX))
+}
+
+/// Compare the OBIS codes seen in a telegram against the expected list,
+/// returning the unexpected and the missing codes. `*255` style suffixes on
+/// expected codes are ignored, like in parsing.
+fn compare_obis<'a>(
+    seen: &'a BTreeSet<String>,
+    expected: &'a [impl AsRef<str>],
+) -> (Vec<&'a str>, Vec<&'a str>) {
+    let expected: BTreeSet<&str> = expected
+        .iter()
+        .map(|code| code.as_ref().split('*').next().unwrap_or(code.as_ref()))
+        .collect();
+    let unexpected = seen
+        .iter()
+        .map(String::as_str)
+        .filter(|code| !expected.contains(code))
+        .collect();
+    let missing = expected
+        .into_iter()
+        .filter(|code| !seen.contains(*code))
+        .collect();
+    (unexpected, missing)
+}
+
+/// Log a complete telegram and check its BCC if `verify` is set. Logging
+/// happens before validation so rejected telegrams can be inspected too.
+fn finish_telegram(
+    raw: &[u8],
+    frame: &[u8],
+    verify: bool,
+    options: &TelegramOptions,
+) -> Result<()> {
+    if let Some(path) = &options.raw_log {
+        if let Err(e) = append_raw_log(path, raw) {
+            warn!("Failed to write raw telegram log {}: {}", path.display(), e);
+        }
+    }
+    if verify && !verify_bcc(frame) {
+        bail!("Telegram BCC mismatch");
+    }
+    Ok(())
+}
+
+/// Append a raw telegram to `path` behind a `#` timestamp header. The header
+/// has no OBIS syntax, so the file can be replayed with `--source`.
+fn append_raw_log(path: &Path, raw: &[u8]) -> io::Result<()> {
+    let mut entry = format!("# {}\r\n", Local::now().to_rfc3339()).into_bytes();
+    entry.extend_from_slice(raw);
+    if !raw.ends_with(b"\n") {
+        entry.extend_from_slice(b"\r\n");
+    }
+
+    // One write per telegram so readers on several ports don't interleave
+    std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?
+        .write_all(&entry)
+}
+
+/// Verify the block check character of a Mode C frame. The BCC is the XOR of
+/// every byte after STX up to and including ETX, and is the last byte of the frame.
+pub fn verify_bcc(frame: &[u8]) -> bool {
+    let start = frame
+        .iter()
+        .position(|&b| b == STX)
+        .map_or(0, |pos| pos + 1);
+    let Some((&bcc, body)) = frame[start..].split_last() else {
+        return false;
     };
+    if body.last() != Some(&ETX) {
+        return false;
+    }
+    body.iter().fold(0, |acc, b| acc ^ b) == bcc
+}
+
+/// Check the CRC16 after the `end` character (`!`) of a DSMR telegram
+/// against the one computed over everything from the `start` character (`/`)
+/// of the identification line through the end character.
+fn verify_dsmr_crc(raw: &[u8], start: char, end: char) -> Result<()> {
+    let (mut start_bytes, mut end_bytes) = ([0; 4], [0; 4]);
+    let start_bytes = start.encode_utf8(&mut start_bytes).as_bytes();
+    let end_bytes = end.encode_utf8(&mut end_bytes).as_bytes();
+    let start = raw
+        .windows(start_bytes.len())
+        .position(|w| w == start_bytes)
+        .context("No identification line to compute the telegram CRC over")?;
+    let end = raw
+        .windows(end_bytes.len())
+        .rposition(|w| w == end_bytes)
+        .map(|pos| pos + end_bytes.len() - 1)
+        .context("No end of telegram to compute the CRC up to")?;
+    let received = std::str::from_utf8(&raw[end + 1..]).unwrap_or("").trim();
+    let expected = u16::from_str_radix(received, 16)
+        .ok()
+        .filter(|_| received.len() == 4)
+        .with_context(|| format!("Missing or malformed telegram CRC: {:?}", received))?;
+    let computed = crc16(&raw[start..=end]);
+    if computed != expected {
+        bail!(
+            "Telegram CRC mismatch: received {:04X}, computed {:04X}",
+            expected,
+            computed
+        );
+    }
+    Ok(())
+}
+
+/// CRC16 with the reflected polynomial 0xA001 and zero initial value
+/// (CRC-16/ARC), as used by DSMR P1 telegrams.
+fn crc16(data: &[u8]) -> u16 {
+    data.iter().fold(0u16, |crc, &byte| {
+        (0..8).fold(crc ^ u16::from(byte), |crc, _| {
+            if crc & 1 != 0 {
+                (crc >> 1) ^ 0xA001
+            } else {
+                crc >> 1
+            }
+        })
+    })
+}
+
+/// Decode a meter clock value `YYMMDDhhmmssX` (X = S/W for summer/winter
+/// time) into an ISO-8601 timestamp with the UTC offset of `tz`, or of the
+/// host when None. Returns None if malformed or nonexistent in that zone.
+fn decode_meter_timestamp(raw: &str, tz: Option<Tz>) -> Option<String> {
+    let raw = raw.trim();
+    let digits = raw.trim_end_matches(['S', 'W', 's', 'w']);
+    if digits.len() != 12 {
+        return None;
+    }
+    // The flag settles the hour that occurs twice when DST ends
+    let summer = !raw.ends_with(['W', 'w']);
+    let time = NaiveDateTime::parse_from_str(digits, "%y%m%d%H%M%S").ok()?;
+    let time = match tz {
+        Some(tz) => pick_dst(tz.from_local_datetime(&time), summer)?,
+        None => pick_dst(Local.from_local_datetime(&time), summer)?,
+    };
+    Some(time.format("%Y-%m-%dT%H:%M:%S%:z").to_string())
+}
+
+/// Resolve a local meter time, taking the earlier (summer time) instant of
+/// an ambiguous one unless the meter says it's on winter time.
+fn pick_dst<Z: TimeZone>(
+    time: LocalResult<DateTime<Z>>,
+    summer: bool,
+) -> Option<DateTime<FixedOffset>> {
+    match time {
+        LocalResult::Single(time) => Some(time.fixed_offset()),
+        LocalResult::Ambiguous(early, late) => {
+            Some(if summer { early } else { late }.fixed_offset())
+        }
+        LocalResult::None => None,
+    }
+}
+
+/// Parse a single OBIS data line like `1-0:1.8.0(0011404.409*kWh)` and
+/// populate the field `map` assigns to its code. Returns the OBIS code
+/// (without `*255` suffix) of a well-formed line, known or not.
+pub fn parse_obis_line<'a>(
+    line: &'a str,
+    reading: &mut MeterReading,
+    map: &ObisMap,
+    tz: Option<Tz>,
+) -> Option<&'a str> {
+    let open = line.find('(')?;
+    let raw_code = &line[..open];
+    // DSMR lines may carry several groups, e.g. a capture time and a volume,
+    // or a power failure log of event times and durations
+    let groups: Vec<&str> = line[open..]
+        .split_inclusive(')')
+        .filter_map(|group| group.trim().strip_prefix('(')?.strip_suffix(')'))
+        .collect();
+    let &raw_value = groups.first()?;
 
     // Strip *255 or similar suffixes from the OBIS code (e.g. "1-0:1.8.0*255" → "1-0:1.8.0")
     let code = raw_code.split('*').next().unwrap_or(raw_code);
 
-    let value_str = raw_value
-        .replace("*kWh", "")
-        .replace("*kW", "")
-        .replace("*V", "")
-        .replace("*A", "")
-        .replace("*Hz", "");
+    // The value is the first group that is a number once its `*unit` is cut
+    // off; timestamps like 101209113020W never are
+    let measured = groups.iter().find_map(|group| {
+        let (number, unit) = group.split_once('*').unwrap_or((group, ""));
+        Some((parse_value(number)?, unit.trim()))
+    });
+    let parsed = measured.map(|(value, _)| value);
 
-    let parsed: Option<f64> = value_str.trim().parse().ok();
+    if let Some(field) = map.get(code) {
+        if let Some(v) = parsed {
+            field.set(reading, v);
+        }
+        return Some(code);
+    }
 
     match code {
-        "1-0:1.8.0" => {
-            if let Some(v) = parsed {
-                reading.consumption_total_kwh = v;
-            }
-        }
-        "1-0:1.8.1" => {
-            if let Some(v) = parsed {
-                reading.consumption_t1_kwh = v;
-            }
-        }
-        "1-0:1.8.2" => {
-            if let Some(v) = parsed {
-                reading.consumption_t2_kwh = v;
-            }
-        }
-        "1-0:2.8.0" => {
-            if let Some(v) = parsed {
-                reading.production_total_kwh = v;
-            }
-        }
-        "1-0:2.8.1" => {
-            if let Some(v) = parsed {
-                reading.production_t1_kwh = v;
-            }
-        }
-        "1-0:2.8.2" => {
-            if let Some(v) = parsed {
-                reading.production_t2_kwh = v;
-            }
-        }
-        "1-0:32.7.0" => {
-            if let Some(v) = parsed {
-                reading.phase1_voltage = v;
-            }
-        }
-        "1-0:52.7.0" => {
-            if let Some(v) = parsed {
-                reading.phase2_voltage = v;
-            }
-        }
-        "1-0:72.7.0" => {
-            if let Some(v) = parsed {
-                reading.phase3_voltage = v;
-            }
-        }
-        "1-0:31.7.0" => {
-            if let Some(v) = parsed {
-                reading.phase1_current = v;
-            }
-        }
-        "1-0:51.7.0" => {
-            if let Some(v) = parsed {
-                reading.phase2_current = v;
-            }
-        }
-        "1-0:71.7.0" => {
-            if let Some(v) = parsed {
-                reading.phase3_current = v;
-            }
+        "0-0:1.0.0" => {
+            reading.meter_timestamp = decode_meter_timestamp(raw_value, tz).unwrap_or_default();
         }
-        "1-0:14.7.0" => {
-            if let Some(v) = parsed {
-                reading.frequency = v;
-            }
+        // Tariff code without a unit, e.g. (0002)
+        "0-0:96.14.0" => {
+            reading.active_tariff = raw_value.trim().parse().ok();
         }
-        "1-0:33.7.0" => {
+        // Grid quality event counters, also plain integers, e.g. (00004)
+        "1-0:32.32.0" => reading.phase1_voltage_sags = raw_value.trim().parse().ok(),
+        "1-0:52.32.0" => reading.phase2_voltage_sags = raw_value.trim().parse().ok(),
+        "1-0:72.32.0" => reading.phase3_voltage_sags = raw_value.trim().parse().ok(),
+        "1-0:32.36.0" => reading.phase1_voltage_swells = raw_value.trim().parse().ok(),
+        "1-0:52.36.0" => reading.phase2_voltage_swells = raw_value.trim().parse().ok(),
+        "1-0:72.36.0" => reading.phase3_voltage_swells = raw_value.trim().parse().ok(),
+        "0-0:96.7.21" => reading.power_failures = raw_value.trim().parse().ok(),
+        // Serial number as hex encoded ASCII, e.g. (4B384547...)
+        "0-0:96.1.1" => match decode_hex_ascii(raw_value) {
+            Some(id) => reading.equipment_id = id,
+            None => debug!("Ignoring malformed equipment ID: {}", raw_value),
+        },
+        // Consumer message as hex encoded UTF-8, usually empty: ()
+        "0-0:96.13.0" => match decode_hex(raw_value).map(String::from_utf8) {
+            Some(Ok(text)) => reading.text_message = text,
+            _ => debug!("Ignoring malformed text message: {}", raw_value),
+        },
+        // Gas meter on M-Bus channel n: (capture time)(volume*m3)
+        code if code.starts_with("0-") && code.ends_with(":24.2.1") => {
             if let Some(v) = parsed {
-                reading.phase1_pf = v;
+                reading.gas_total_m3 = Some(v);
             }
-        }
-        "1-0:53.7.0" => {
-            if let Some(v) = parsed {
-                reading.phase2_pf = v;
+            if groups.len() > 1 {
+                reading.gas_timestamp = decode_meter_timestamp(groups[0], tz).unwrap_or_default();
             }
         }
-        "1-0:73.7.0" => {
-            if let Some(v) = parsed {
-                reading.phase3_pf = v;
+        _ => match measured {
+            Some((value, unit)) => {
+                reading.other_obis.insert(
+                    code.to_string(),
+                    ObisValue {
+                        value,
+                        unit: unit.to_string(),
+                    },
+                );
             }
-        }
-        _ => {
-            debug!("Ignoring OBIS code: {}", code);
-        }
+            None => debug!("Ignoring OBIS code: {}", code),
+        },
+    }
+
+    Some(code)
+}
+
+/// Decode a string of hex digit pairs, as DSMR meters send text values in.
+/// Returns None for odd lengths or non-hex digits.
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    let hex = hex.trim();
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Decode a hex string of printable ASCII characters, as DSMR meters send
+/// their equipment identifier. Returns None if empty, malformed or holding
+/// control characters.
+fn decode_hex_ascii(hex: &str) -> Option<String> {
+    let bytes = decode_hex(hex).filter(|bytes| !bytes.is_empty())?;
+    bytes
+        .into_iter()
+        .map(|byte| (byte.is_ascii_graphic() || byte == b' ').then_some(byte as char))
+        .collect()
+}
+
+/// Parse a numeric OBIS value with its unit already removed. Values may be
+/// signed (`-00.234`, `+01.500`) or use exponent notation (`1.2E3`).
+fn parse_value(value: &str) -> Option<f64> {
+    let value = value.trim();
+    let (negative, digits) = match value.as_bytes().first() {
+        Some(b'-') => (true, &value[1..]),
+        Some(b'+') => (false, &value[1..]),
+        _ => (false, value),
+    };
+    // A sign on the digits themselves ("+-1", "--1") is not a number
+    if digits.starts_with(['+', '-']) {
+        return None;
     }
+    let magnitude: f64 = digits.parse().ok()?;
+    Some(if negative { -magnitude } else { magnitude })
 }
 
 #[cfg(test)]
@@ -236,54 +1062,138 @@ mod tests {
     use super::*;
     use crate::meter::MeterReading;
 
+    fn parse_line<'a>(line: &'a str, reading: &mut MeterReading) -> Option<&'a str> {
+        parse_obis_line(
+            line,
+            reading,
+            &ObisMap::default(),
+            Some(Tz::Europe__Amsterdam),
+        )
+    }
+
     #[test]
     fn parse_consumption_total() {
         let mut r = MeterReading::default();
-        parse_obis_line("1-0:1.8.0*255(0002686.675*kWh)", &mut r);
+        parse_line("1-0:1.8.0*255(0002686.675*kWh)", &mut r);
         assert!((r.consumption_total_kwh - 2686.675).abs() < 0.001);
     }
 
     #[test]
     fn parse_consumption_t1() {
         let mut r = MeterReading::default();
-        parse_obis_line("1-0:1.8.1*255(0001200.000*kWh)", &mut r);
+        parse_line("1-0:1.8.1*255(0001200.000*kWh)", &mut r);
         assert!((r.consumption_t1_kwh - 1200.0).abs() < 0.001);
     }
 
     #[test]
     fn parse_consumption_t2() {
         let mut r = MeterReading::default();
-        parse_obis_line("1-0:1.8.2*255(0002686.675*kWh)", &mut r);
+        parse_line("1-0:1.8.2*255(0002686.675*kWh)", &mut r);
         assert!((r.consumption_t2_kwh - 2686.675).abs() < 0.001);
     }
 
     #[test]
     fn parse_production_total() {
         let mut r = MeterReading::default();
-        parse_obis_line("1-0:2.8.0*255(0009354.299*kWh)", &mut r);
+        parse_line("1-0:2.8.0*255(0009354.299*kWh)", &mut r);
         assert!((r.production_total_kwh - 9354.299).abs() < 0.001);
     }
 
     #[test]
     fn parse_production_t1() {
         let mut r = MeterReading::default();
-        parse_obis_line("1-0:2.8.1*255(0004000.000*kWh)", &mut r);
+        parse_line("1-0:2.8.1*255(0004000.000*kWh)", &mut r);
         assert!((r.production_t1_kwh - 4000.0).abs() < 0.001);
     }
 
     #[test]
     fn parse_production_t2() {
         let mut r = MeterReading::default();
-        parse_obis_line("1-0:2.8.2*255(0009354.299*kWh)", &mut r);
+        parse_line("1-0:2.8.2*255(0009354.299*kWh)", &mut r);
         assert!((r.production_t2_kwh - 9354.299).abs() < 0.001);
     }
 
+    #[test]
+    fn parse_reactive_import_total() {
+        let mut r = MeterReading::default();
+        parse_line("1-0:3.8.0*255(0000123.456*kvarh)", &mut r);
+        assert!((r.reactive_import_total_kvarh - 123.456).abs() < 0.001);
+    }
+
+    #[test]
+    fn parse_reactive_export_total() {
+        let mut r = MeterReading::default();
+        parse_line("1-0:4.8.0*255(0000987.654*kvarh)", &mut r);
+        assert!((r.reactive_export_total_kvarh - 987.654).abs() < 0.001);
+    }
+
+    #[test]
+    fn parse_import_export_power() {
+        let mut r = MeterReading::default();
+        parse_line("1-0:1.7.0*255(00.250*kW)", &mut r);
+        parse_line("1-0:2.7.0*255(01.500*kW)", &mut r);
+        assert_eq!(r.import_power_kw, Some(0.25));
+        assert_eq!(r.export_power_kw, Some(1.5));
+    }
+
+    #[test]
+    fn total_power_prefers_measured_import() {
+        let mut r = MeterReading::default();
+        parse_line("1-0:1.7.0(01.193*kW)", &mut r);
+        parse_line("1-0:32.7.0*255(230.0*V)", &mut r);
+        parse_line("1-0:31.7.0*255(1.00*A)", &mut r);
+        parse_line("1-0:33.7.0*255(1.000)", &mut r);
+        r.calculate_power(&PowerSettings::default());
+        assert_eq!(r.import_power_kw, Some(1.193));
+        assert!((r.phase1_power - 230.0).abs() < 0.01);
+        assert!((r.total_power - 1193.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn total_power_uses_combined_power() {
+        let mut r = MeterReading::default();
+        parse_line("1-0:15.7.0*255(00.812*kW)", &mut r);
+        parse_line("1-0:90.7.0*255(003.55*A)", &mut r);
+        parse_line("1-0:32.7.0*255(230.0*V)", &mut r);
+        parse_line("1-0:31.7.0*255(1.00*A)", &mut r);
+        parse_line("1-0:33.7.0*255(1.000)", &mut r);
+        r.calculate_power(&PowerSettings::default());
+        assert_eq!(r.combined_power_kw, Some(0.812));
+        assert_eq!(r.total_current, Some(3.55));
+        assert!((r.total_power - 812.0).abs() < 0.01);
+        assert!((r.net_power - 812.0).abs() < 0.01);
+
+        // Measured import/export still takes precedence
+        parse_line("1-0:1.7.0(01.193*kW)", &mut r);
+        r.calculate_power(&PowerSettings::default());
+        assert!((r.total_power - 1193.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn net_power_from_measured_values() {
+        let mut r = MeterReading::default();
+        parse_line("1-0:1.7.0*255(00.250*kW)", &mut r);
+        parse_line("1-0:2.7.0*255(01.500*kW)", &mut r);
+        r.calculate_power(&PowerSettings::default());
+        assert!((r.net_power - -1250.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn net_power_falls_back_to_estimate() {
+        let mut r = MeterReading::default();
+        parse_line("1-0:32.7.0*255(230.0*V)", &mut r);
+        parse_line("1-0:31.7.0*255(1.00*A)", &mut r);
+        parse_line("1-0:33.7.0*255(1.000)", &mut r);
+        r.calculate_power(&PowerSettings::default());
+        assert!((r.net_power - 230.0).abs() < 0.01);
+    }
+
     #[test]
     fn parse_voltage() {
         let mut r = MeterReading::default();
-        parse_obis_line("1-0:32.7.0*255(231.3*V)", &mut r);
-        parse_obis_line("1-0:52.7.0*255(233.2*V)", &mut r);
-        parse_obis_line("1-0:72.7.0*255(231.4*V)", &mut r);
+        parse_line("1-0:32.7.0*255(231.3*V)", &mut r);
+        parse_line("1-0:52.7.0*255(233.2*V)", &mut r);
+        parse_line("1-0:72.7.0*255(231.4*V)", &mut r);
         assert!((r.phase1_voltage - 231.3).abs() < 0.01);
         assert!((r.phase2_voltage - 233.2).abs() < 0.01);
         assert!((r.phase3_voltage - 231.4).abs() < 0.01);
@@ -292,9 +1202,9 @@ mod tests {
     #[test]
     fn parse_current() {
         let mut r = MeterReading::default();
-        parse_obis_line("1-0:31.7.0*255(0.98*A)", &mut r);
-        parse_obis_line("1-0:51.7.0*255(0.10*A)", &mut r);
-        parse_obis_line("1-0:71.7.0*255(0.64*A)", &mut r);
+        parse_line("1-0:31.7.0*255(0.98*A)", &mut r);
+        parse_line("1-0:51.7.0*255(0.10*A)", &mut r);
+        parse_line("1-0:71.7.0*255(0.64*A)", &mut r);
         assert!((r.phase1_current - 0.98).abs() < 0.001);
         assert!((r.phase2_current - 0.10).abs() < 0.001);
         assert!((r.phase3_current - 0.64).abs() < 0.001);
@@ -303,39 +1213,268 @@ mod tests {
     #[test]
     fn parse_frequency() {
         let mut r = MeterReading::default();
-        parse_obis_line("1-0:14.7.0*255(50.03*Hz)", &mut r);
+        parse_line("1-0:14.7.0*255(50.03*Hz)", &mut r);
         assert!((r.frequency - 50.03).abs() < 0.001);
     }
 
     #[test]
     fn parse_without_star_suffix() {
         let mut r = MeterReading::default();
-        parse_obis_line("1-0:1.8.0(0011404.409*kWh)", &mut r);
+        parse_line("1-0:1.8.0(0011404.409*kWh)", &mut r);
         assert!((r.consumption_total_kwh - 11404.409).abs() < 0.001);
     }
 
     #[test]
     fn parse_power_factor() {
         let mut r = MeterReading::default();
-        parse_obis_line("1-0:33.7.0*255(0.950)", &mut r);
-        parse_obis_line("1-0:53.7.0*255(0.800)", &mut r);
-        parse_obis_line("1-0:73.7.0*255(0.750)", &mut r);
+        parse_line("1-0:33.7.0*255(0.950)", &mut r);
+        parse_line("1-0:53.7.0*255(0.800)", &mut r);
+        parse_line("1-0:73.7.0*255(0.750)", &mut r);
         assert!((r.phase1_pf - 0.950).abs() < 0.001);
         assert!((r.phase2_pf - 0.800).abs() < 0.001);
         assert!((r.phase3_pf - 0.750).abs() < 0.001);
     }
 
+    #[test]
+    fn read_telegram_restarts_on_second_ident() {
+        let telegram = "/ISk5MT174-0001\r\n\r\n\
+            1-0:1.8.0*255(0002686.675*kWh)\r\n\
+            1-0:32.7.0*255(231.3*V)\r\n\
+            /ISk5MT174-0001\r\n\r\n\
+            1-0:2.8.0*255(0009354.299*kWh)\r\n\
+            !\r\n";
+        let reading = read_telegram(
+            telegram.as_bytes(),
+            "ISk5MT174",
+            false,
+            &TelegramOptions::default(),
+            &MeterProfile::GENERIC,
+        )
+        .unwrap();
+        assert_eq!(reading.device_id, "ISk5MT174-0001");
+        assert_eq!(reading.consumption_total_kwh, 0.0);
+        assert_eq!(reading.phase1_voltage, 0.0);
+        assert!((reading.production_total_kwh - 9354.299).abs() < 0.001);
+    }
+
+    #[test]
+    fn read_telegram_any_line_ending() {
+        let crlf = "/ISk5MT174-0001\r\n\r\n\
+            1-0:1.8.0*255(0002686.675*kWh)\r\n\
+            1-0:32.7.0*255(231.3*V)\r\n\
+            1-0:31.7.0*255(0.98*A)\r\n\
+            !\r\n";
+        let read = |telegram: &str| {
+            read_telegram(
+                telegram.as_bytes(),
+                "ISk5MT174",
+                false,
+                &TelegramOptions::default(),
+                &MeterProfile::GENERIC,
+            )
+            .unwrap()
+        };
+        let expected = read(crlf);
+        assert_eq!(expected.phase1_voltage, 231.3);
+        for telegram in [crlf.replace("\r\n", "\r"), crlf.replace("\r\n", "\n")] {
+            let reading = read(&telegram);
+            assert!(reading.same_values(&expected), "{:?}", telegram);
+        }
+
+        // A CR-only end line is followed right away by the ETX/BCC trailer
+        let frame = framed_telegram("1-0:1.8.0*255(0002686.675*kWh)\r!\r");
+        let options = TelegramOptions {
+            verify_bcc: true,
+            ..Default::default()
+        };
+        let reading = read_telegram(
+            frame.as_slice(),
+            "ISk5MT174",
+            false,
+            &options,
+            &MeterProfile::GENERIC,
+        )
+        .unwrap();
+        assert_eq!(reading.consumption_total_kwh, 2686.675);
+    }
+
+    #[test]
+    fn read_telegram_skips_invalid_utf8_line() {
+        let mut telegram = b"/ISk5MT174-0001\r\n\r\n".to_vec();
+        telegram.extend_from_slice(b"1-0:1.8.0*255(0002686.675*kWh)\r\n");
+        telegram.extend_from_slice(b"1-0:32.7.0*255(23\xff\xfe1.3*V)\r\n");
+        telegram.extend_from_slice(b"1-0:31.7.0*255(0.98*A)\r\n!\r\n");
+        let reading = read_telegram(
+            telegram.as_slice(),
+            "ISk5MT174",
+            false,
+            &TelegramOptions::default(),
+            &MeterProfile::GENERIC,
+        )
+        .unwrap();
+        assert!((reading.consumption_total_kwh - 2686.675).abs() < 0.001);
+        assert_eq!(reading.phase1_voltage, 0.0);
+        assert_eq!(reading.phase1_current, 0.98);
+        assert_eq!(reading.malformed_line_count, 1);
+    }
+
+    #[test]
+    fn parse_active_tariff() {
+        let mut r = MeterReading::default();
+        assert_eq!(parse_line("0-0:96.14.0(0002)", &mut r), Some("0-0:96.14.0"));
+        assert_eq!(r.active_tariff, Some(2));
+        parse_line("0-0:96.14.0*255(0001)", &mut r);
+        assert_eq!(r.active_tariff, Some(1));
+    }
+
+    #[test]
+    fn parse_meter_timestamp() {
+        let mut r = MeterReading::default();
+        parse_line("0-0:1.0.0(260227172626W)", &mut r);
+        assert_eq!(r.meter_timestamp, "2026-02-27T17:26:26+01:00");
+
+        parse_line("0-0:1.0.0(260715080000S)", &mut r);
+        assert_eq!(r.meter_timestamp, "2026-07-15T08:00:00+02:00");
+    }
+
+    #[test]
+    fn meter_timestamp_dst_flag() {
+        // 02:30 occurs twice on the night DST ends
+        assert_eq!(
+            decode_meter_timestamp("261025023000S", Some(Tz::Europe__Amsterdam)).as_deref(),
+            Some("2026-10-25T02:30:00+02:00")
+        );
+        assert_eq!(
+            decode_meter_timestamp("261025023000W", Some(Tz::Europe__Amsterdam)).as_deref(),
+            Some("2026-10-25T02:30:00+01:00")
+        );
+        // and not at all on the night it starts
+        assert_eq!(
+            decode_meter_timestamp("260329023000W", Some(Tz::Europe__Amsterdam)),
+            None
+        );
+        assert_eq!(
+            decode_meter_timestamp("260227172626W", Some(Tz::America__New_York)).as_deref(),
+            Some("2026-02-27T17:26:26-05:00")
+        );
+    }
+
+    #[test]
+    fn malformed_meter_timestamp_left_empty() {
+        let mut r = MeterReading::default();
+        parse_line("0-0:1.0.0(2602271726W)", &mut r);
+        assert_eq!(r.meter_timestamp, "");
+        parse_line("0-0:1.0.0(261399999999W)", &mut r);
+        assert_eq!(r.meter_timestamp, "");
+    }
+
     #[test]
     fn unknown_code_ignored() {
         let mut r = MeterReading::default();
-        parse_obis_line("0-0:C.1.6*255(FDF5)", &mut r);
+        parse_line("0-0:C.1.6*255(FDF5)", &mut r);
         assert_eq!(r.consumption_total_kwh, 0.0);
+        assert!(r.other_obis.is_empty());
+    }
+
+    #[test]
+    fn parse_apparent_power() {
+        let mut r = MeterReading::default();
+        parse_line("1-0:9.7.0(00.412*kVA)", &mut r);
+        parse_line("1-0:49.7.0(00.120*kVA)", &mut r);
+        assert_eq!(r.apparent_power_kva, Some(0.412));
+        assert_eq!(r.phase2_apparent_power_kva, Some(0.12));
+        assert_eq!(r.phase1_apparent_power_kva, None);
+    }
+
+    #[test]
+    fn parse_grid_event_counters() {
+        let mut r = MeterReading::default();
+        parse_line("1-0:32.32.0(00004)", &mut r);
+        parse_line("1-0:72.36.0(00012)", &mut r);
+        parse_line("0-0:96.7.21(00007)", &mut r);
+        parse_line("1-0:52.32.0(0000x)", &mut r);
+        assert_eq!(r.phase1_voltage_sags, Some(4));
+        assert_eq!(r.phase3_voltage_swells, Some(12));
+        assert_eq!(r.power_failures, Some(7));
+        assert_eq!(r.phase2_voltage_sags, None);
+        assert_eq!(r.phase1_voltage_swells, None);
+        assert!(r.other_obis.is_empty());
+    }
+
+    #[test]
+    fn decodes_equipment_id() {
+        let mut r = MeterReading::default();
+        let line = "0-0:96.1.1(4B384547303034303436333935353037)";
+        assert_eq!(parse_line(line, &mut r), Some("0-0:96.1.1"));
+        assert_eq!(r.equipment_id, "K8EG004046395507");
+        // An all-digit serial must not end up as a number in other_obis
+        parse_line("0-0:96.1.1(3132333435)", &mut r);
+        assert_eq!(r.equipment_id, "12345");
+        assert!(r.other_obis.is_empty());
+
+        let mut r = MeterReading::default();
+        parse_line("0-0:96.1.1(4B3)", &mut r);
+        parse_line("0-0:96.1.1(ZZ)", &mut r);
+        parse_line("0-0:96.1.1(0A0D)", &mut r);
+        assert_eq!(r.equipment_id, "");
+    }
+
+    #[test]
+    fn decodes_text_message() {
+        let mut r = MeterReading::default();
+        let line = "0-0:96.13.0(4F6E646572686F75642032372F303220E282AC)";
+        assert_eq!(parse_line(line, &mut r), Some("0-0:96.13.0"));
+        assert_eq!(r.text_message, "Onderhoud 27/02 €");
+        assert!(r.other_obis.is_empty());
+
+        parse_line("0-0:96.13.0()", &mut r);
+        assert_eq!(r.text_message, "");
+
+        r.text_message = "kept".to_string();
+        parse_line("0-0:96.13.0(C3)", &mut r);
+        parse_line("0-0:96.13.0(4B3)", &mut r);
+        assert_eq!(r.text_message, "kept");
+    }
+
+    #[test]
+    fn unknown_codes_keep_value_and_unit() {
+        let mut r = MeterReading::default();
+        assert_eq!(parse_line("0-0:96.7.9(00004)", &mut r), Some("0-0:96.7.9"));
+        parse_line("1-0:5.8.0*255(0000012.500*kvarh)", &mut r);
+        parse_line(
+            "1-0:99.97.0(2)(0-0:96.7.19)(101208152415W)(0000000240*s)(101208151004W)(0000000301*s)",
+            &mut r,
+        );
+        parse_line("0-1:24.2.3(231015120000S)(00012.345*Nm3)", &mut r);
+
+        let value = |code: &str| {
+            let v = &r.other_obis[code];
+            (v.value, v.unit.as_str())
+        };
+        assert_eq!(value("0-0:96.7.9"), (4.0, ""));
+        assert_eq!(value("1-0:5.8.0"), (12.5, "kvarh"));
+        // The event count, not the first duration
+        assert_eq!(value("1-0:99.97.0"), (2.0, ""));
+        // A unit outside the old hardcoded set, after a capture time
+        assert_eq!(value("0-1:24.2.3"), (12.345, "Nm3"));
+    }
+
+    #[test]
+    fn any_unit_is_stripped_from_known_codes() {
+        let mut r = MeterReading::default();
+        parse_line("1-0:1.7.0(01.193*kW)", &mut r);
+        parse_line("1-0:32.7.0(230.1*V)", &mut r);
+        parse_line("1-0:1.8.1(000123.456*KWH)", &mut r);
+        assert_eq!(r.import_power_kw, Some(1.193));
+        assert_eq!(r.phase1_voltage, 230.1);
+        assert_eq!(r.consumption_t1_kwh, 123.456);
+        assert!(r.other_obis.is_empty());
     }
 
     #[test]
     fn malformed_line_ignored() {
         let mut r = MeterReading::default();
-        parse_obis_line("garbage without parens", &mut r);
+        parse_line("garbage without parens", &mut r);
         assert_eq!(r.consumption_total_kwh, 0.0);
     }
 
@@ -366,7 +1505,14 @@ mod tests {
 1-0:73.7.0*255(1.000)\r\n\
 !\r\n";
         let reader = std::io::BufReader::new(telegram.as_bytes());
-        let reading = read_telegram(reader, "ISk5MT174", false).unwrap();
+        let reading = read_telegram(
+            reader,
+            "ISk5MT174",
+            false,
+            &TelegramOptions::default(),
+            &MeterProfile::GENERIC,
+        )
+        .unwrap();
         assert_eq!(reading.device_id, "ISk5MT174-0001");
         assert!((reading.consumption_total_kwh - 2686.675).abs() < 0.001);
         assert!((reading.production_total_kwh - 9354.299).abs() < 0.001);
@@ -378,4 +1524,631 @@ mod tests {
         assert!((reading.phase3_power - 148.10).abs() < 0.1);
         assert!((reading.total_power - 398.09).abs() < 0.1);
     }
+
+    /// Build a Mode C telegram with STX/ETX framing and a valid BCC.
+    fn framed_telegram(data: &str) -> Vec<u8> {
+        let mut bytes = b"/ISk5MT174-0001\r\n".to_vec();
+        bytes.push(STX);
+        bytes.extend_from_slice(data.as_bytes());
+        bytes.push(ETX);
+        let bcc = bytes[bytes.iter().position(|&b| b == STX).unwrap() + 1..]
+            .iter()
+            .fold(0, |acc, b| acc ^ b);
+        bytes.push(bcc);
+        bytes
+    }
+
+    #[test]
+    fn crc16_check_value() {
+        assert_eq!(crc16(b"123456789"), 0xBB3D);
+    }
+
+    #[test]
+    fn read_telegram_checks_dsmr_crc() {
+        let body = "/KFM5KAIFA-METER\r\n\r\n1-0:1.8.1(001234.567*kWh)\r\n!";
+        let options = TelegramOptions {
+            crc_check: Some(CrcCheck::Dsmr),
+            ..Default::default()
+        };
+        let read = |telegram: String| {
+            read_telegram(
+                telegram.as_bytes(),
+                "KFM5",
+                false,
+                &options,
+                &MeterProfile::DSMR,
+            )
+        };
+
+        let crc = crc16(body.as_bytes());
+        let reading = read(format!("{}{:04X}\r\n", body, crc)).unwrap();
+        assert!((reading.consumption_t1_kwh - 1234.567).abs() < 0.001);
+        assert_eq!(reading.telegram_crc, format!("{:04X}", crc));
+
+        let err = read(format!("{}{:04X}\r\n", body, crc ^ 1)).unwrap_err();
+        assert!(err.to_string().contains("CRC mismatch"), "{}", err);
+        assert!(read(format!("{}\r\n", body)).is_err());
+    }
+
+    #[test]
+    fn read_telegram_checks_dsmr_crc_with_custom_delimiters() {
+        let body = "#KFM5KAIFA-METER\r\n\r\n1-0:1.8.1(001234.567*kWh)\r\n$";
+        let options = TelegramOptions {
+            crc_check: Some(CrcCheck::Dsmr),
+            start: '#',
+            end: '$',
+            ..Default::default()
+        };
+        let read = |telegram: String| {
+            read_telegram(
+                telegram.as_bytes(),
+                "KFM5",
+                false,
+                &options,
+                &MeterProfile::DSMR,
+            )
+        };
+
+        let crc = crc16(body.as_bytes());
+        let reading = read(format!("{}{:04X}\r\n", body, crc)).unwrap();
+        assert!((reading.consumption_t1_kwh - 1234.567).abs() < 0.001);
+        let err = read(format!("{}{:04X}\r\n", body, crc ^ 1)).unwrap_err();
+        assert!(err.to_string().contains("CRC mismatch"), "{}", err);
+    }
+
+    #[test]
+    fn verify_bcc_valid() {
+        assert!(verify_bcc(&framed_telegram(
+            "1-0:1.8.0*255(0002686.675*kWh)\r\n!\r\n"
+        )));
+    }
+
+    #[test]
+    fn verify_bcc_corrupted() {
+        let mut frame = framed_telegram("1-0:1.8.0*255(0002686.675*kWh)\r\n!\r\n");
+        let pos = frame.iter().position(|&b| b == b'6').unwrap();
+        frame[pos] = b'7';
+        assert!(!verify_bcc(&frame));
+    }
+
+    #[test]
+    fn verify_bcc_missing_etx() {
+        assert!(!verify_bcc(b"\x021-0:1.8.0(1*kWh)\r\n!\r\n"));
+    }
+
+    #[test]
+    fn read_telegram_with_valid_bcc() {
+        let frame =
+            framed_telegram("1-0:0.0.0*255(88381140)\r\n1-0:1.8.0*255(0002686.675*kWh)\r\n!\r\n");
+        let options = TelegramOptions {
+            verify_bcc: true,
+            ..Default::default()
+        };
+        let reading = read_telegram(
+            frame.as_slice(),
+            "ISk5MT174",
+            false,
+            &options,
+            &MeterProfile::GENERIC,
+        )
+        .unwrap();
+        assert!((reading.consumption_total_kwh - 2686.675).abs() < 0.001);
+    }
+
+    #[test]
+    fn read_telegram_after_ident_read_up_to_cr() {
+        // Without STX the BCC covers everything after the identification
+        let mut bytes = b"/ISk5MT174-0001\r\n1-0:1.8.0*255(0002686.675*kWh)\r\n!\r\n".to_vec();
+        bytes.push(ETX);
+        let bcc = bytes[17..].iter().fold(0, |acc, b| acc ^ b);
+        bytes.push(bcc);
+
+        let mut stream = bytes.as_slice();
+        let mut line = Vec::new();
+        read_line(&mut stream, &mut line).unwrap();
+        let ident = check_ident(&line, '/', "ISk5MT174", None).unwrap();
+        let options = TelegramOptions {
+            verify_bcc: true,
+            ..Default::default()
+        };
+        let reading =
+            read_telegram(stream, &ident, true, &options, &MeterProfile::GENERIC).unwrap();
+        assert_eq!(reading.device_id, "ISk5MT174-0001");
+        assert!((reading.consumption_total_kwh - 2686.675).abs() < 0.001);
+    }
+
+    #[test]
+    fn read_telegram_skips_bcc_for_dsmr_profile() {
+        // Nothing follows the `!` line, reading a trailer would hit EOF
+        let telegram = "/KFM5KAIFA-METER\r\n\r\n1-0:1.8.1(001234.567*kWh)\r\n!6A3C\r\n";
+        let options = TelegramOptions {
+            verify_bcc: true,
+            ..Default::default()
+        };
+        let reading = read_telegram(
+            telegram.as_bytes(),
+            "KFM5",
+            false,
+            &options,
+            &MeterProfile::DSMR,
+        )
+        .unwrap();
+        assert!((reading.consumption_t1_kwh - 1234.567).abs() < 0.001);
+        assert_eq!(reading.telegram_crc, "6A3C");
+    }
+
+    #[test]
+    fn read_telegram_rejects_bad_bcc() {
+        let mut frame =
+            framed_telegram("1-0:0.0.0*255(88381140)\r\n1-0:1.8.0*255(0002686.675*kWh)\r\n!\r\n");
+        *frame.last_mut().unwrap() ^= 0x01;
+        let options = TelegramOptions {
+            verify_bcc: true,
+            ..Default::default()
+        };
+        assert!(read_telegram(
+            frame.as_slice(),
+            "ISk5MT174",
+            false,
+            &options,
+            &MeterProfile::GENERIC
+        )
+        .is_err());
+
+        // Lenient mode ignores the trailer entirely
+        let reading = read_telegram(
+            frame.as_slice(),
+            "ISk5MT174",
+            false,
+            &TelegramOptions::default(),
+            &MeterProfile::GENERIC,
+        )
+        .unwrap();
+        assert!((reading.consumption_total_kwh - 2686.675).abs() < 0.001);
+    }
+
+    #[test]
+    fn from_reader_reads_successive_telegrams() {
+        let dump = "\
+/ISk5MT174-0001\r\n\
+1-0:1.8.0*255(0002686.675*kWh)\r\n\
+!\r\n\
+/ISk5MT174-0001\r\n\
+1-0:1.8.0*255(0002686.700*kWh)\r\n\
+!\r\n";
+        let reader = std::io::Cursor::new(dump.as_bytes().to_vec());
+        let mut conn =
+            MeterConnection::from_reader(reader, "ISk5MT174", TelegramOptions::default());
+        let first = conn.read().unwrap();
+        let second = conn.read().unwrap();
+        assert!((first.consumption_total_kwh - 2686.675).abs() < 0.001);
+        assert!((second.consumption_total_kwh - 2686.700).abs() < 0.001);
+        assert!(conn.read().is_err());
+    }
+
+    #[test]
+    fn ident_must_match_device_id() {
+        assert_eq!(
+            check_ident(b"/ISk5MT174-0001\r\n", '/', "ISk5MT174", None).unwrap(),
+            "ISk5MT174-0001"
+        );
+        let err = check_ident(b"/LGZ5ZMD310CR\r\n", '/', "ISk5MT174", None).unwrap_err();
+        assert!(err.to_string().contains("LGZ5ZMD310CR"), "{}", err);
+        assert!(check_ident(b"", '/', "ISk5MT174", None).is_err());
+        assert_eq!(
+            check_ident(b"#ISk5MT174-0001\r\n", '#', "ISk5MT174", None).unwrap(),
+            "ISk5MT174-0001"
+        );
+
+        let pattern = Regex::new("^ISk5MT174-0002$").unwrap();
+        let err =
+            check_ident(b"/ISk5MT174-0001\r\n", '/', "ISk5MT174", Some(&pattern)).unwrap_err();
+        assert!(err.to_string().contains("^ISk5MT174-0002$"), "{}", err);
+    }
+
+    #[test]
+    fn disconnect_errors_detected() {
+        let enodev = anyhow::Error::new(io::Error::from_raw_os_error(ENODEV))
+            .context("Failed to read line from serial port");
+        assert!(is_disconnect(&enodev));
+
+        let reset = anyhow::Error::new(io::Error::from(io::ErrorKind::ConnectionReset));
+        assert!(is_disconnect(&reset));
+
+        let timeout = anyhow::Error::new(io::Error::from(io::ErrorKind::TimedOut));
+        assert!(!is_disconnect(&timeout));
+
+        assert!(!is_disconnect(&anyhow::anyhow!("Unexpected device: /XYZ")));
+    }
+
+    #[test]
+    fn reconnect_reopens_file() {
+        let path =
+            std::env::temp_dir().join(format!("energymon-reconnect-{}.txt", std::process::id()));
+        std::fs::write(
+            &path,
+            "/ISk5MT174-0001\r\n1-0:1.8.0*255(0002686.675*kWh)\r\n!\r\n",
+        )
+        .unwrap();
+
+        let mut conn =
+            MeterConnection::open_file(&path, "ISk5MT174", TelegramOptions::default()).unwrap();
+        assert!(conn.read().is_ok());
+        assert!(conn.read().is_err());
+
+        conn.reconnect().unwrap();
+        assert!(conn.read().is_ok());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn reconnect_reader_fails_and_stays_disconnected() {
+        let mut conn = MeterConnection::from_reader(
+            std::io::Cursor::new(Vec::new()),
+            "ISk5MT174",
+            TelegramOptions::default(),
+        );
+        assert!(conn.reconnect().is_err());
+        let err = conn.read().unwrap_err();
+        assert!(is_disconnect(&err));
+    }
+
+    #[test]
+    fn raw_log_can_be_replayed() {
+        let path =
+            std::env::temp_dir().join(format!("energymon-raw-log-{}.txt", std::process::id()));
+        let options = TelegramOptions {
+            raw_log: Some(path.clone()),
+            ..Default::default()
+        };
+        let telegram = "/ISk5MT174-0001\r\n1-0:1.8.0*255(0002686.675*kWh)\r\n!\r\n";
+        for _ in 0..2 {
+            read_telegram(
+                telegram.as_bytes(),
+                "ISk5MT174",
+                false,
+                &options,
+                &MeterProfile::GENERIC,
+            )
+            .unwrap();
+        }
+
+        let logged = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(logged.matches(telegram).count(), 2);
+        assert!(logged.starts_with("# "));
+
+        let mut conn =
+            MeterConnection::open_file(&path, "ISk5MT174", TelegramOptions::default()).unwrap();
+        assert!((conn.read().unwrap().consumption_total_kwh - 2686.675).abs() < 0.001);
+        assert!(conn.read().is_ok());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn read_telegram_with_stx_etx_framing() {
+        let mut telegram = b"/ISk5MT174-0001\r\n\r\n\x021-0:1.8.0*255(0002686.675*kWh)\r\n\
+1-0:2.8.0*255(0000012.500*kWh)\r\n\x03"
+            .to_vec();
+        let bcc = telegram[telegram.iter().position(|&b| b == STX).unwrap() + 1..]
+            .iter()
+            .fold(0, |acc, b| acc ^ b);
+        telegram.push(bcc);
+
+        let options = TelegramOptions {
+            verify_bcc: true,
+            ..Default::default()
+        };
+        let reading = read_telegram(
+            telegram.as_slice(),
+            "ISk5MT174",
+            false,
+            &options,
+            &MeterProfile::GENERIC,
+        )
+        .unwrap();
+        assert!((reading.consumption_total_kwh - 2686.675).abs() < 0.001);
+        assert!((reading.production_total_kwh - 12.5).abs() < 0.001);
+
+        // A leftover ETX/BCC from the previous telegram is skipped
+        let mut stream = b"\x03\x42".to_vec();
+        stream.extend_from_slice(&telegram);
+        let reading = read_telegram(
+            stream.as_slice(),
+            "ISk5MT174",
+            false,
+            &TelegramOptions::default(),
+            &MeterProfile::GENERIC,
+        )
+        .unwrap();
+        assert!((reading.consumption_total_kwh - 2686.675).abs() < 0.001);
+    }
+
+    #[test]
+    fn read_telegram_skips_foreign_device() {
+        let stream = "\
+/XYZ5OTHER-0001\r\n\
+1-0:1.8.0*255(0000001.000*kWh)\r\n\
+!\r\n\
+/ISk5MT174-0001\r\n\
+1-0:1.8.0*255(0002686.675*kWh)\r\n\
+!\r\n";
+        let reading = read_telegram(
+            stream.as_bytes(),
+            "ISk5MT174",
+            false,
+            &TelegramOptions::default(),
+            &MeterProfile::GENERIC,
+        )
+        .unwrap();
+        assert_eq!(reading.device_id, "ISk5MT174-0001");
+        assert!((reading.consumption_total_kwh - 2686.675).abs() < 0.001);
+
+        let strict = TelegramOptions {
+            strict_device_id: true,
+            ..Default::default()
+        };
+        let err = read_telegram(
+            stream.as_bytes(),
+            "ISk5MT174",
+            false,
+            &strict,
+            &MeterProfile::GENERIC,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("Unexpected device"));
+    }
+
+    #[test]
+    fn read_telegram_matches_device_id_in_any_case() {
+        let stream = "\
+/isk5MT174-0001\r\n\
+1-0:1.8.0*255(0002686.675*kWh)\r\n\
+!\r\n";
+        let reading = read_telegram(
+            stream.as_bytes(),
+            "ISk5MT174",
+            false,
+            &TelegramOptions::default(),
+            &MeterProfile::GENERIC,
+        )
+        .unwrap();
+        assert_eq!(reading.device_id, "isk5MT174-0001");
+
+        // A pattern that doesn't match skips the telegram like another device
+        let options = TelegramOptions {
+            device_pattern: Some(Regex::new("^ISk5MT174-0002$").unwrap()),
+            strict_device_id: true,
+            ..Default::default()
+        };
+        let err = read_telegram(
+            stream.as_bytes(),
+            "ISk5MT174",
+            false,
+            &options,
+            &MeterProfile::GENERIC,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("Unexpected device"));
+    }
+
+    #[test]
+    fn read_telegram_gives_up_after_foreign_limit() {
+        let stream = "/XYZ5OTHER-0001\r\n!\r\n".repeat(MAX_FOREIGN_TELEGRAMS + 1);
+        let err = read_telegram(
+            stream.as_bytes(),
+            "ISk5MT174",
+            false,
+            &TelegramOptions::default(),
+            &MeterProfile::GENERIC,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("other devices"));
+    }
+
+    #[test]
+    fn parse_gas_meter() {
+        let mut r = MeterReading::default();
+        parse_line("0-1:24.2.1(260227170000W)(02345.678*m3)", &mut r);
+        assert_eq!(r.gas_total_m3, Some(2345.678));
+        assert_eq!(r.gas_timestamp, "2026-02-27T17:00:00+01:00");
+    }
+
+    #[test]
+    fn parse_unterminated_group_ignored() {
+        let mut r = MeterReading::default();
+        parse_line("1-0:1.8.0*255(0002686.675*kWh", &mut r);
+        assert_eq!(r.consumption_total_kwh, 0.0);
+    }
+
+    #[test]
+    fn compare_obis_reports_differences() {
+        let mut seen = BTreeSet::new();
+        let mut r = MeterReading::default();
+        for line in [
+            "1-0:1.8.0*255(0002686.675*kWh)",
+            "1-0:2.8.0*255(0000012.500*kWh)",
+            "1-0:96.1.0*255(ABC)",
+            "garbage",
+        ] {
+            if let Some(code) = parse_line(line, &mut r) {
+                seen.insert(code.to_string());
+            }
+        }
+        let expected = ["1-0:1.8.0*255", "1-0:2.8.0", "1-0:32.7.0"].map(String::from);
+        let (unexpected, missing) = compare_obis(&seen, &expected);
+        assert_eq!(unexpected, ["1-0:96.1.0"]);
+        assert_eq!(missing, ["1-0:32.7.0"]);
+    }
+
+    #[test]
+    fn read_telegram_counts_malformed_lines() {
+        let telegram = "\
+/ISk5MT174-0001\r\n\
+1-0:1.8.0*255(0002686.675*kWh)\r\n\
+1-0:2.8.0*255(00000\r\n\
+\x7f\x12garbage\r\n\
+!\r\n";
+        let reading = read_telegram(
+            telegram.as_bytes(),
+            "ISk5MT174",
+            false,
+            &TelegramOptions::default(),
+            &MeterProfile::GENERIC,
+        )
+        .unwrap();
+        assert_eq!(reading.malformed_line_count, 2);
+        assert!((reading.consumption_total_kwh - 2686.675).abs() < 0.001);
+    }
+
+    #[test]
+    fn read_telegram_custom_delimiters() {
+        let options = TelegramOptions {
+            start: '#',
+            end: '$',
+            ..Default::default()
+        };
+        let telegram = "\
+#ISk5MT174-0001\r\n\
+1-0:1.8.0*255(0002686.675*kWh)\r\n\
+/not an identification line\r\n\
+!not the end\r\n\
+$\r\n";
+        let reading = read_telegram(
+            telegram.as_bytes(),
+            "ISk5MT174",
+            false,
+            &options,
+            &MeterProfile::GENERIC,
+        )
+        .unwrap();
+        assert_eq!(reading.device_id, "ISk5MT174-0001");
+        assert_eq!(reading.consumption_total_kwh, 2686.675);
+        assert_eq!(reading.malformed_line_count, 2);
+    }
+
+    /// A stream that repeats `chunk` forever, like a meter that never ends.
+    struct Endless(&'static [u8], usize);
+
+    impl Read for Endless {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            for byte in buf.iter_mut() {
+                *byte = self.0[self.1 % self.0.len()];
+                self.1 += 1;
+            }
+            Ok(buf.len())
+        }
+    }
+
+    #[test]
+    fn read_telegram_bounded_on_endless_stream() {
+        let options = TelegramOptions::default();
+
+        let lines = BufReader::new(Endless(b"1-0:1.8.0*255(0002686.675*kWh)\r\n", 0));
+        let err =
+            read_telegram(lines, "ISk5MT174", true, &options, &MeterProfile::GENERIC).unwrap_err();
+        assert!(err.to_string().contains("after 200 lines"), "{}", err);
+
+        let noise = BufReader::new(Endless(b"\x55", 0));
+        let err =
+            read_telegram(noise, "ISk5MT174", true, &options, &MeterProfile::GENERIC).unwrap_err();
+        assert!(err.to_string().contains("longer than"), "{}", err);
+    }
+
+    #[test]
+    fn measured_phase_power() {
+        let mut r = MeterReading::default();
+        for line in [
+            "1-0:32.7.0(231.0*V)",
+            "1-0:31.7.0(2.0*A)",
+            "1-0:33.7.0(0.9)",
+            "1-0:21.7.0(00.512*kW)",
+            "1-0:22.7.0(00.000*kW)",
+            "1-0:41.7.0(00.000*kW)",
+            "1-0:42.7.0(01.250*kW)",
+            "1-0:52.7.0(230.0*V)",
+            "1-0:72.7.0(230.0*V)",
+            "1-0:71.7.0(1.0*A)",
+            "1-0:73.7.0(1.0)",
+        ] {
+            parse_line(line, &mut r);
+        }
+        assert_eq!(r.phase1_import_power_kw, Some(0.512));
+        assert_eq!(r.phase2_export_power_kw, Some(1.25));
+        r.calculate_power(&PowerSettings::default());
+        assert_eq!(r.phase1_power, 512.0);
+        assert_eq!(r.phase2_power, -1250.0);
+        // No measured value for phase 3, estimated from V × I × PF
+        assert_eq!(r.phase3_power, 230.0);
+        assert_eq!(r.total_power, 512.0 - 1250.0 + 230.0);
+    }
+
+    #[test]
+    fn negative_power_factor_is_leading() {
+        let mut r = MeterReading::default();
+        for line in [
+            "1-0:32.7.0(230.0*V)",
+            "1-0:31.7.0(2.0*A)",
+            "1-0:33.7.0(-0.500)",
+        ] {
+            parse_line(line, &mut r);
+        }
+        r.calculate_power(&PowerSettings::default());
+        assert_eq!(r.phase1_power, 230.0);
+        assert!(r.pf_leading);
+        assert!(r.phase1_reactive_var < 0.0);
+
+        // The total PF decides when the meter reports one
+        parse_line("1-0:13.7.0(0.970)", &mut r);
+        assert_eq!(r.total_pf, Some(0.97));
+        r.calculate_power(&PowerSettings::default());
+        assert!(!r.pf_leading);
+
+        parse_line("1-0:13.7.0(-0.970)", &mut r);
+        r.calculate_power(&PowerSettings::default());
+        assert!(r.pf_leading);
+    }
+
+    #[test]
+    fn parse_signed_net_power() {
+        let mut r = MeterReading::default();
+        parse_line("1-0:16.7.0(-00.234*kW)", &mut r);
+        assert_eq!(r.net_power_kw, Some(-0.234));
+        parse_line("1-0:16.7.0(+01.500*kW)", &mut r);
+        assert_eq!(r.net_power_kw, Some(1.5));
+        parse_line("1-0:16.7.0(00.812*kW)", &mut r);
+        assert_eq!(r.net_power_kw, Some(0.812));
+
+        parse_line("1-0:16.7.0(-00.234*kW)", &mut r);
+        r.calculate_power(&PowerSettings::default());
+        assert!((r.net_power - -234.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn parse_value_formats() {
+        assert_eq!(parse_value("+0002686.675"), Some(2686.675));
+        assert_eq!(parse_value("-1.5E1"), Some(-15.0));
+        assert_eq!(parse_value("2.5e-3"), Some(0.0025));
+        assert_eq!(parse_value("+-1"), None);
+        assert_eq!(parse_value(""), None);
+    }
+
+    #[test]
+    fn persistent_session_reader_keeps_next_telegram() {
+        let stream = "/ISk5MT174-0001\r\n1-0:1.8.0*255(0002686.675*kWh)\r\n!\r\n\
+                      /ISk5MT174-0001\r\n1-0:1.8.0*255(0002686.700*kWh)\r\n!\r\n";
+        let mut port = stream.as_bytes();
+        let options = TelegramOptions::default();
+
+        let read = |port: &mut &[u8]| {
+            read_telegram(
+                serial_reader(port, true),
+                "ISk5MT174",
+                false,
+                &options,
+                &MeterProfile::GENERIC,
+            )
+        };
+        assert_eq!(read(&mut port).unwrap().consumption_total_kwh, 2686.675);
+        assert_eq!(read(&mut port).unwrap().consumption_total_kwh, 2686.7);
+    }
 }
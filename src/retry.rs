@@ -0,0 +1,72 @@
+use std::time::Duration;
+
+/// Upper bound for a single backoff delay.
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Exponential backoff for retrying failed reads: `base`, `2 × base`,
+/// `4 × base`, ... capped at [`MAX_BACKOFF`].
+pub struct Backoff {
+    base: Duration,
+    max_retries: u32,
+    failures: u32,
+}
+
+impl Backoff {
+    pub fn new(base: Duration, max_retries: u32) -> Self {
+        Self {
+            base,
+            max_retries,
+            failures: 0,
+        }
+    }
+
+    /// Forget previous failures, e.g. after a successful read.
+    pub fn reset(&mut self) {
+        self.failures = 0;
+    }
+
+    /// Record a failure and return the delay before retrying, or `None` once
+    /// all retries are used up. Exhausting the retries resets the counter.
+    pub fn next_delay(&mut self) -> Option<Duration> {
+        if self.failures >= self.max_retries {
+            self.failures = 0;
+            return None;
+        }
+
+        let factor = 1u32.checked_shl(self.failures).unwrap_or(u32::MAX);
+        self.failures += 1;
+        Some(self.base.saturating_mul(factor).min(MAX_BACKOFF))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delays_double_until_exhausted() {
+        let mut backoff = Backoff::new(Duration::from_secs(1), 3);
+        assert_eq!(backoff.next_delay(), Some(Duration::from_secs(1)));
+        assert_eq!(backoff.next_delay(), Some(Duration::from_secs(2)));
+        assert_eq!(backoff.next_delay(), Some(Duration::from_secs(4)));
+        assert_eq!(backoff.next_delay(), None);
+        // Starts over after giving up
+        assert_eq!(backoff.next_delay(), Some(Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn delays_are_capped() {
+        let mut backoff = Backoff::new(Duration::from_secs(10), 100);
+        let last = (0..40).filter_map(|_| backoff.next_delay()).last();
+        assert_eq!(last, Some(MAX_BACKOFF));
+    }
+
+    #[test]
+    fn reset_after_success() {
+        let mut backoff = Backoff::new(Duration::from_millis(500), 3);
+        backoff.next_delay();
+        backoff.next_delay();
+        backoff.reset();
+        assert_eq!(backoff.next_delay(), Some(Duration::from_millis(500)));
+    }
+}
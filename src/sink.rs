@@ -0,0 +1,76 @@
+use anyhow::Result;
+use std::sync::Arc;
+
+use crate::meter::MeterReading;
+
+/// A destination readings are handed to after every telegram: MQTT, a CSV
+/// file, stdout, or one of your own. Sinks are shared by all reader threads.
+pub trait ReadingSink: Send + Sync {
+    /// Short name used in log messages, e.g. `mqtt` or `csv`.
+    fn name(&self) -> &str;
+
+    /// Publish one reading. A failing sink is logged on its own and doesn't
+    /// keep the reading from the other sinks.
+    fn publish(&self, reading: &MeterReading) -> Result<()>;
+
+    /// Publish the telegrams of a `--burst` together. Sinks without a batch
+    /// format publish them one by one.
+    fn publish_batch(&self, readings: &[MeterReading]) -> Result<()> {
+        readings
+            .iter()
+            .try_for_each(|reading| self.publish(reading))
+    }
+}
+
+impl<T: ReadingSink + ?Sized> ReadingSink for Arc<T> {
+    fn name(&self) -> &str {
+        (**self).name()
+    }
+
+    fn publish(&self, reading: &MeterReading) -> Result<()> {
+        (**self).publish(reading)
+    }
+
+    fn publish_batch(&self, readings: &[MeterReading]) -> Result<()> {
+        (**self).publish_batch(readings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct Recorder(Mutex<Vec<String>>);
+
+    impl ReadingSink for Recorder {
+        fn name(&self) -> &str {
+            "recorder"
+        }
+
+        fn publish(&self, reading: &MeterReading) -> Result<()> {
+            self.0.lock().unwrap().push(reading.device_id.clone());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn shared_sink_forwards() {
+        let recorder = Arc::new(Recorder::default());
+        let sinks: Vec<Box<dyn ReadingSink>> = vec![Box::new(Arc::clone(&recorder))];
+        let reading = MeterReading {
+            device_id: "ISk5MT174-0001".to_string(),
+            ..Default::default()
+        };
+        for sink in &sinks {
+            assert_eq!(sink.name(), "recorder");
+            sink.publish(&reading).unwrap();
+        }
+        assert_eq!(*recorder.0.lock().unwrap(), ["ISk5MT174-0001"]);
+
+        let burst = [reading.clone(), reading];
+        sinks[0].publish_batch(&burst).unwrap();
+        assert_eq!(recorder.0.lock().unwrap().len(), 3);
+    }
+}
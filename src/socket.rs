@@ -0,0 +1,107 @@
+use anyhow::{Context, Result};
+use log::{info, warn};
+use std::io::{ErrorKind, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use crate::meter::MeterReading;
+use crate::mqtt::{self, PayloadFormat};
+use crate::sink::ReadingSink;
+
+/// Streams readings as newline-delimited JSON to every client connected to
+/// a Unix domain socket, a lightweight local alternative to MQTT.
+pub struct SocketServer {
+    clients: Arc<Mutex<Vec<UnixStream>>>,
+    format: PayloadFormat,
+}
+
+impl SocketServer {
+    /// Bind the socket, replacing a stale socket file from a previous run,
+    /// and accept clients from a background thread.
+    pub fn bind(path: &Path, format: PayloadFormat) -> Result<Self> {
+        if path.exists() {
+            std::fs::remove_file(path)
+                .with_context(|| format!("Failed to remove stale socket {}", path.display()))?;
+        }
+        let listener = UnixListener::bind(path)
+            .with_context(|| format!("Failed to bind Unix socket {}", path.display()))?;
+        info!("Streaming readings on {}", path.display());
+
+        let clients = Arc::new(Mutex::new(Vec::new()));
+        let accepted = Arc::clone(&clients);
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                match stream.and_then(|s| s.set_nonblocking(true).map(|_| s)) {
+                    Ok(stream) => accepted
+                        .lock()
+                        .expect("socket clients lock poisoned")
+                        .push(stream),
+                    Err(e) => warn!("Failed to accept socket client: {}", e),
+                }
+            }
+        });
+
+        Ok(Self { clients, format })
+    }
+
+    /// Send one JSON frame to every client. Writes never block the reader:
+    /// a client that can't take the whole frame right away, or has gone
+    /// away, is disconnected.
+    pub fn send(&self, json: &serde_json::Value) {
+        let mut frame = json.to_string();
+        frame.push('\n');
+
+        let mut clients = self.clients.lock().expect("socket clients lock poisoned");
+        clients.retain_mut(|client| match client.write_all(frame.as_bytes()) {
+            Ok(()) => true,
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                warn!("Socket client is not keeping up, disconnecting it");
+                false
+            }
+            Err(_) => false,
+        });
+    }
+}
+
+impl ReadingSink for SocketServer {
+    fn name(&self) -> &str {
+        "unix socket"
+    }
+
+    fn publish(&self, reading: &MeterReading) -> Result<()> {
+        self.send(&mqtt::reading_json(reading, &self.format)?);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn streams_frames_to_clients() {
+        let path = std::env::temp_dir().join(format!("energymon-{}.sock", std::process::id()));
+        let server = SocketServer::bind(&path, PayloadFormat::default()).unwrap();
+        let client = UnixStream::connect(&path).unwrap();
+
+        // Wait for the accept thread to register the client
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while server.clients.lock().unwrap().is_empty() && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        server.send(&serde_json::json!({"total_power": 430.36}));
+
+        let mut line = String::new();
+        BufReader::new(client).read_line(&mut line).unwrap();
+        assert_eq!(line, "{\"total_power\":430.36}\n");
+
+        // A closed client is dropped on the next send
+        server.send(&serde_json::json!({}));
+        server.send(&serde_json::json!({}));
+        assert!(server.clients.lock().unwrap().is_empty());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
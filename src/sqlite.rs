@@ -0,0 +1,228 @@
+use anyhow::{Context, Result};
+use log::info;
+use rusqlite::types::Value;
+use rusqlite::Connection;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::meter::MeterReading;
+use crate::sink::ReadingSink;
+
+/// How long an insert waits for a reader such as Grafana to release the
+/// database before giving up on the reading.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Inserts readings into the `readings` table of a SQLite database, one
+/// column per scalar field of `MeterReading`.
+pub struct SqliteWriter {
+    connection: Mutex<Connection>,
+    insert: String,
+}
+
+impl SqliteWriter {
+    /// Open the database, creating the table and its timestamp index if
+    /// needed and adding columns for fields newer than the table.
+    pub fn open(path: &Path) -> Result<Self> {
+        let connection = Connection::open(path)
+            .with_context(|| format!("Failed to open SQLite database {}", path.display()))?;
+        // WAL lets dashboards read while a reading is written; the busy
+        // timeout retries the remaining lock conflicts
+        connection
+            .busy_timeout(BUSY_TIMEOUT)
+            .and_then(|_| connection.pragma_update(None, "journal_mode", "WAL"))
+            .context("Failed to configure SQLite database")?;
+
+        let columns = columns()?;
+        let definitions: Vec<_> = columns
+            .iter()
+            .map(|(name, kind)| format!("\"{}\" {}", name, kind))
+            .collect();
+        connection
+            .execute_batch(&format!(
+                "CREATE TABLE IF NOT EXISTS readings ({});
+                 CREATE INDEX IF NOT EXISTS readings_timestamp ON readings (timestamp);",
+                definitions.join(", ")
+            ))
+            .context("Failed to create readings table")?;
+
+        let existing = connection
+            .prepare("SELECT name FROM pragma_table_info('readings')")
+            .and_then(|mut query| {
+                query
+                    .query_map([], |row| row.get::<_, String>(0))?
+                    .collect::<rusqlite::Result<Vec<_>>>()
+            })
+            .context("Failed to inspect readings table")?;
+        for ((name, _), definition) in columns.iter().zip(&definitions) {
+            if !existing.contains(name) {
+                info!("Adding column {} to {}", name, path.display());
+                connection
+                    .execute(
+                        &format!("ALTER TABLE readings ADD COLUMN {}", definition),
+                        [],
+                    )
+                    .with_context(|| format!("Failed to add column {}", name))?;
+            }
+        }
+
+        let names: Vec<_> = columns
+            .iter()
+            .map(|(name, _)| format!("\"{}\"", name))
+            .collect();
+        let placeholders = vec!["?"; names.len()].join(", ");
+        Ok(Self {
+            connection: Mutex::new(connection),
+            insert: format!(
+                "INSERT INTO readings ({}) VALUES ({})",
+                names.join(", "),
+                placeholders
+            ),
+        })
+    }
+
+    /// Insert one reading as a row.
+    pub fn write(&self, reading: &MeterReading) -> Result<()> {
+        let connection = self.connection.lock().expect("SQLite lock poisoned");
+        // Compiled once and kept in the connection's statement cache
+        let mut insert = connection
+            .prepare_cached(&self.insert)
+            .context("Failed to prepare insert")?;
+        insert
+            .execute(rusqlite::params_from_iter(row(reading)?))
+            .context("Failed to insert reading")?;
+        Ok(())
+    }
+}
+
+impl ReadingSink for SqliteWriter {
+    fn name(&self) -> &str {
+        "sqlite"
+    }
+
+    fn publish(&self, reading: &MeterReading) -> Result<()> {
+        self.write(reading)
+    }
+}
+
+/// Scalar fields of a reading with their SQLite column type, in declaration
+/// order. Nested values like `other_obis` have no column.
+fn columns() -> Result<Vec<(String, &'static str)>> {
+    let value =
+        serde_json::to_value(MeterReading::default()).context("Failed to serialize reading")?;
+    Ok(value
+        .as_object()
+        .into_iter()
+        .flatten()
+        .filter_map(|(name, value)| {
+            let kind = match value {
+                serde_json::Value::String(_) => "TEXT",
+                serde_json::Value::Bool(_) => "INTEGER",
+                serde_json::Value::Number(n) if n.is_f64() => "REAL",
+                serde_json::Value::Number(_) => "INTEGER",
+                // Fields the meter may not report
+                serde_json::Value::Null => "NUMERIC",
+                _ => return None,
+            };
+            Some((name.clone(), kind))
+        })
+        .collect())
+}
+
+/// The values of a reading in the order of `columns`.
+fn row(reading: &MeterReading) -> Result<Vec<Value>> {
+    let value = serde_json::to_value(reading).context("Failed to serialize reading")?;
+    Ok(value
+        .as_object()
+        .into_iter()
+        .flatten()
+        .filter_map(|(_, value)| {
+            Some(match value {
+                serde_json::Value::String(s) => Value::Text(s.clone()),
+                serde_json::Value::Bool(b) => Value::Integer(i64::from(*b)),
+                serde_json::Value::Number(n) => match n.as_i64() {
+                    Some(i) => Value::Integer(i),
+                    None => Value::Real(n.as_f64()?),
+                },
+                serde_json::Value::Null => Value::Null,
+                _ => return None,
+            })
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh database path in its own directory, which also takes the
+    /// WAL files.
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("energymon-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir.join("readings.db")
+    }
+
+    #[test]
+    fn inserts_readings() {
+        let path = temp_path("sqlite");
+        let writer = SqliteWriter::open(&path).unwrap();
+        for kwh in [2686.675, 2686.7] {
+            let reading = MeterReading {
+                device_id: "ISk5MT174-0001".to_string(),
+                consumption_total_kwh: kwh,
+                power_failures: Some(3),
+                timestamp: "2026-02-27 17:26:26.675439".to_string(),
+                ..Default::default()
+            };
+            writer.write(&reading).unwrap();
+        }
+        drop(writer);
+
+        let connection = Connection::open(&path).unwrap();
+        let rows: Vec<(String, f64, i64, Option<f64>)> = connection
+            .prepare(
+                "SELECT device_id, consumption_total_kwh, power_failures, gas_total_m3 \
+                 FROM readings ORDER BY rowid",
+            )
+            .unwrap()
+            .query_map([], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+            })
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        assert_eq!(
+            rows,
+            [
+                ("ISk5MT174-0001".to_string(), 2686.675, 3, None),
+                ("ISk5MT174-0001".to_string(), 2686.7, 3, None),
+            ]
+        );
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn adds_missing_columns() {
+        let path = temp_path("sqlite-upgrade");
+        Connection::open(&path)
+            .unwrap()
+            .execute_batch("CREATE TABLE readings (device_id TEXT, timestamp TEXT)")
+            .unwrap();
+
+        let writer = SqliteWriter::open(&path).unwrap();
+        writer.write(&MeterReading::default()).unwrap();
+        let connection = writer.connection.lock().unwrap();
+        let count: i64 = connection
+            .query_row(
+                "SELECT count(*) FROM pragma_table_info('readings') WHERE name = 'total_power'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(count, 1);
+        drop(connection);
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,84 @@
+use log::{info, warn};
+
+use crate::meter::MeterReading;
+
+/// Flags energy counters that stopped advancing while the meter still
+/// reports power, the sign of a hung meter firmware that keeps repeating
+/// its last telegram.
+pub struct StaleDetector {
+    limit: u32,
+    /// Consumption and production totals (kWh) of the previous reading.
+    previous: Option<(f64, f64)>,
+    /// Consecutive readings with power but unchanged totals.
+    unchanged: u32,
+}
+
+impl StaleDetector {
+    /// Readings become stale after `limit` consecutive readings without change.
+    pub fn new(limit: u32) -> Self {
+        Self {
+            limit,
+            previous: None,
+            unchanged: 0,
+        }
+    }
+
+    /// Set `stale` on the reading, warning when the counters freeze and
+    /// noting when they move again. Both directions are tracked, as only
+    /// one counter advances while power flows.
+    pub fn apply(&mut self, reading: &mut MeterReading) {
+        let totals = (reading.consumption_total_kwh, reading.production_total_kwh);
+        let was_stale = self.unchanged >= self.limit;
+        if self.previous == Some(totals) && reading.total_power != 0.0 {
+            self.unchanged += 1;
+        } else {
+            self.unchanged = 0;
+        }
+        self.previous = Some(totals);
+
+        let stale = self.unchanged >= self.limit;
+        if stale && !was_stale {
+            warn!(
+                "Energy counters of {} unchanged for {} readings despite {} W, the meter may be stuck",
+                reading.device_id, self.unchanged, reading.total_power
+            );
+        } else if was_stale && !stale {
+            info!("Energy counters of {} are moving again", reading.device_id);
+        }
+        reading.stale = Some(stale);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stale(detector: &mut StaleDetector, kwh: f64, power: f64) -> Option<bool> {
+        let mut reading = MeterReading {
+            consumption_total_kwh: kwh,
+            total_power: power,
+            ..Default::default()
+        };
+        detector.apply(&mut reading);
+        reading.stale
+    }
+
+    #[test]
+    fn flags_frozen_counter_with_power() {
+        let mut detector = StaleDetector::new(2);
+        assert_eq!(stale(&mut detector, 100.0, 500.0), Some(false));
+        assert_eq!(stale(&mut detector, 100.0, 500.0), Some(false));
+        assert_eq!(stale(&mut detector, 100.0, 500.0), Some(true));
+        assert_eq!(stale(&mut detector, 100.0, 500.0), Some(true));
+        assert_eq!(stale(&mut detector, 100.1, 500.0), Some(false));
+    }
+
+    #[test]
+    fn idle_meter_is_not_stale() {
+        let mut detector = StaleDetector::new(1);
+        assert_eq!(stale(&mut detector, 100.0, 0.0), Some(false));
+        assert_eq!(stale(&mut detector, 100.0, 0.0), Some(false));
+        // Power resets the count, it doesn't continue it
+        assert_eq!(stale(&mut detector, 100.0, 20.0), Some(true));
+    }
+}
@@ -0,0 +1,93 @@
+use anyhow::{Context, Result};
+use log::{info, warn};
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::Path;
+
+use crate::meter::MeterReading;
+
+/// Load the reading saved by a previous run. A missing file is a first run;
+/// an unreadable or corrupt one is logged and treated the same.
+pub fn load(path: &Path) -> Option<MeterReading> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            info!("No state file at {}, starting fresh", path.display());
+            return None;
+        }
+        Err(e) => {
+            warn!(
+                "Failed to read state file {}: {}, starting fresh",
+                path.display(),
+                e
+            );
+            return None;
+        }
+    };
+    match serde_json::from_str(&contents) {
+        Ok(reading) => Some(reading),
+        Err(e) => {
+            warn!(
+                "Corrupt state file {}: {}, starting fresh",
+                path.display(),
+                e
+            );
+            None
+        }
+    }
+}
+
+/// Save the latest reading. It is written to a temporary file next to `path`
+/// and renamed over it, so a crash mid-write never leaves a truncated state.
+pub fn save(path: &Path, reading: &MeterReading) -> Result<()> {
+    let mut tmp_name = path
+        .file_name()
+        .context("State file path has no file name")?
+        .to_owned();
+    tmp_name.push(".tmp");
+    let tmp = path.with_file_name(tmp_name);
+
+    let json = serde_json::to_vec(reading).context("Failed to serialize state")?;
+    let mut file =
+        File::create(&tmp).with_context(|| format!("Failed to create {}", tmp.display()))?;
+    file.write_all(&json)
+        .and_then(|()| file.sync_all())
+        .with_context(|| format!("Failed to write {}", tmp.display()))?;
+    fs::rename(&tmp, path).with_context(|| format!("Failed to replace {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_and_recovers_from_corruption() {
+        let dir = std::env::temp_dir().join(format!("energymon-state-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("state.json");
+        assert_eq!(load(&path), None);
+
+        let reading = MeterReading {
+            device_id: "ISk5MT174-0001".to_string(),
+            consumption_t1_kwh: 1200.5,
+            cost_total: Some(12.34),
+            gas_total_m3: Some(1.5),
+            ..Default::default()
+        };
+        save(&path, &reading).unwrap();
+        assert_eq!(load(&path), Some(reading));
+        assert!(!dir.join("state.json.tmp").exists());
+
+        fs::write(&path, "{\"device_id\": ").unwrap();
+        assert_eq!(load(&path), None);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn loads_state_missing_newer_fields() {
+        let reading: MeterReading =
+            serde_json::from_str(r#"{"device_id": "ISk5MT174-0001", "cost_total": 1.5}"#).unwrap();
+        assert_eq!(reading.cost_total, Some(1.5));
+        assert_eq!(reading.total_pf, None);
+    }
+}